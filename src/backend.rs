@@ -0,0 +1,271 @@
+use anyhow::{anyhow, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::{process::Stdio, sync::Arc, time::Duration};
+
+pub const MUMBLE_API_URL: &str = "https://mumble.stream/speak";
+pub const MUMBLE_VOICES_URL: &str = "https://mumble.stream/voices";
+/// `--` keeps messages starting with `-` from being read as options.
+pub const DEFAULT_COMMAND_TEMPLATE: &str = "espeak-ng --stdout -- {text}";
+/// A local command which takes longer than this is killed.
+pub const COMMAND_TIMEOUT_SECONDS: u64 = 30;
+
+/// The container format of the synthesized audio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+    Ogg,
+    Flac,
+    /// Let the decoder figure it out.
+    Unknown,
+}
+
+impl AudioFormat {
+    pub fn from_mime(mime: &str) -> AudioFormat {
+        match mime.split(';').next().unwrap_or("").trim() {
+            "audio/wav" | "audio/wave" | "audio/x-wav" | "audio/vnd.wave" => AudioFormat::Wav,
+            "audio/mpeg" | "audio/mp3" => AudioFormat::Mp3,
+            "audio/ogg" | "audio/vorbis" => AudioFormat::Ogg,
+            "audio/flac" | "audio/x-flac" => AudioFormat::Flac,
+            _ => AudioFormat::Unknown,
+        }
+    }
+}
+
+/// Encoded audio returned by a backend.
+#[derive(Debug, Clone)]
+pub struct Audio {
+    pub bytes: Vec<u8>,
+    pub format: AudioFormat,
+}
+
+/// A text to speech provider.
+///
+/// Implementations perform a single synthesis attempt; retries and rate limiting
+/// are handled by the caller (see `tts::make_tts_request`).
+pub trait TtsBackend: Send + Sync {
+    /// A human readable name, used in the UI and in the logs.
+    fn name(&self) -> &'static str;
+
+    /// Synthesize `text` using `voice`.
+    fn synthesize<'a>(&'a self, text: &'a str, voice: &'a str) -> BoxFuture<'a, Result<Audio>>;
+
+    /// The names of the voices the backend offers, or nothing if it doesn't have a fixed list.
+    fn voices(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+}
+
+/// The backend configuration stored in `ui::State`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackendKind {
+    Mumble,
+    /// A local program which writes a WAV file to stdout.
+    Command {
+        template: String,
+    },
+}
+
+impl Default for BackendKind {
+    fn default() -> Self {
+        BackendKind::Mumble
+    }
+}
+
+impl BackendKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::Mumble => "vo.codes (mumble.stream)",
+            BackendKind::Command { .. } => "Local command",
+        }
+    }
+
+    pub fn create(&self, client: reqwest::Client) -> Arc<dyn TtsBackend> {
+        match self {
+            BackendKind::Mumble => Arc::new(MumbleBackend::new(client)),
+            BackendKind::Command { template } => Arc::new(CommandBackend::new(template)),
+        }
+    }
+}
+
+/// The vo.codes API, hosted at mumble.stream.
+pub struct MumbleBackend {
+    client: reqwest::Client,
+}
+
+impl MumbleBackend {
+    pub fn new(client: reqwest::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[derive(Deserialize)]
+struct MumbleVoice {
+    slug: String,
+}
+
+#[derive(Serialize)]
+struct MumbleRequest<'a> {
+    text: &'a str,
+    speaker: &'a str,
+}
+
+impl TtsBackend for MumbleBackend {
+    fn name(&self) -> &'static str {
+        BackendKind::Mumble.name()
+    }
+
+    fn synthesize<'a>(&'a self, text: &'a str, voice: &'a str) -> BoxFuture<'a, Result<Audio>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(MUMBLE_API_URL)
+                .json(&MumbleRequest {
+                    text,
+                    speaker: voice,
+                })
+                .send()
+                .await?;
+            log::debug!("{:#?}", response);
+
+            let status = response.status();
+            if !status.is_success() {
+                return Err(anyhow!(
+                    "HTTP {} - {}",
+                    status,
+                    response
+                        .text()
+                        .await
+                        .unwrap_or_else(|_| "<no response body>".to_string())
+                ));
+            }
+
+            let format = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .map(AudioFormat::from_mime)
+                .unwrap_or(AudioFormat::Unknown);
+            let bytes = response.bytes().await?.to_vec();
+
+            Ok(Audio { bytes, format })
+        })
+    }
+
+    fn voices(&self) -> BoxFuture<'_, Result<Vec<String>>> {
+        Box::pin(async move {
+            let voices = self
+                .client
+                .get(MUMBLE_VOICES_URL)
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<Vec<MumbleVoice>>()
+                .await?;
+            Ok(voices.into_iter().map(|voice| voice.slug).collect())
+        })
+    }
+}
+
+/// Runs a local program (e.g. espeak-ng or piper) for every request.
+///
+/// The template is split into arguments before the `{text}` and `{voice}` placeholders
+/// are substituted, so the chat message is never interpreted by a shell.
+pub struct CommandBackend {
+    args: Vec<String>,
+}
+
+impl CommandBackend {
+    pub fn new(template: &str) -> Self {
+        Self {
+            args: split_args(template),
+        }
+    }
+}
+
+impl TtsBackend for CommandBackend {
+    fn name(&self) -> &'static str {
+        "Local command"
+    }
+
+    fn synthesize<'a>(&'a self, text: &'a str, voice: &'a str) -> BoxFuture<'a, Result<Audio>> {
+        Box::pin(async move {
+            let mut args = self
+                .args
+                .iter()
+                .map(|arg| crate::template::render(arg, &[("text", text), ("voice", voice)]));
+            let program = args
+                .next()
+                .ok_or_else(|| anyhow!("The command template is empty"))?;
+
+            // The child is killed when the timed out future is dropped.
+            let output = tokio::process::Command::new(&program)
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .kill_on_drop(true)
+                .output();
+            let output = tokio::time::timeout(Duration::from_secs(COMMAND_TIMEOUT_SECONDS), output)
+                .await
+                .map_err(|_| {
+                    anyhow!(
+                        "`{}` didn't finish within {}s",
+                        program,
+                        COMMAND_TIMEOUT_SECONDS
+                    )
+                })?
+                .map_err(|e| anyhow!("Failed to run `{}`: {}", program, e))?;
+
+            if !output.status.success() {
+                return Err(anyhow!(
+                    "`{}` exited with {} - {}",
+                    program,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                ));
+            }
+            if output.stdout.is_empty() {
+                return Err(anyhow!("`{}` didn't write anything to stdout", program));
+            }
+
+            Ok(Audio {
+                bytes: output.stdout,
+                format: AudioFormat::Wav,
+            })
+        })
+    }
+}
+
+/// Split a command line into arguments, respecting double quotes.
+fn split_args(template: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_arg = false;
+
+    for c in template.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_arg = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+
+    args
+}
@@ -0,0 +1,106 @@
+use futures::Stream;
+use rodio::{source::UniformSourceIterator, Sample, Source};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+use warp::ws::{Message, WebSocket};
+
+/// Every clip is resampled to this, so the segments of an item can be joined.
+pub const SAMPLE_RATE: u32 = 44100;
+pub const CHANNELS: u16 = 2;
+/// How many clips can be waiting to be sent to a slow browser source.
+pub const CHANNEL_CAPACITY: usize = 16;
+
+/// Where the TTS is played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutputMode {
+    /// The local output device.
+    Device,
+    /// The `/audio` browser source, so OBS captures the TTS as its own audio source.
+    Browser,
+}
+
+impl Default for OutputMode {
+    fn default() -> Self {
+        OutputMode::Device
+    }
+}
+
+impl OutputMode {
+    pub const ALL: [OutputMode; 2] = [OutputMode::Device, OutputMode::Browser];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputMode::Device => "Output device",
+            OutputMode::Browser => "OBS browser source",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BrowserAudio {
+    /// A whole queue item, as a WAV file.
+    Play(Arc<Vec<u8>>),
+    /// Stop the clip which is currently playing.
+    Stop,
+}
+
+pub fn channel() -> broadcast::Sender<BrowserAudio> {
+    broadcast::channel(CHANNEL_CAPACITY).0
+}
+
+/// Join `sources` into one WAV file, and return it with its duration.
+pub fn render<S>(sources: Vec<S>) -> (Vec<u8>, Duration)
+where
+    S: Source<Item = f32>,
+{
+    let samples = sources
+        .into_iter()
+        .flat_map(|source| UniformSourceIterator::<_, f32>::new(source, CHANNELS, SAMPLE_RATE))
+        .map(|sample| sample.to_i16())
+        .collect::<Vec<_>>();
+    let frames = samples.len() as u64 / CHANNELS as u64;
+    let duration = Duration::from_millis(frames * 1000 / SAMPLE_RATE as u64);
+    (encode_wav(&samples), duration)
+}
+
+/// A 16-bit PCM WAV file.
+fn encode_wav(samples: &[i16]) -> Vec<u8> {
+    let data_len = (samples.len() * 2) as u32;
+    let block_align = CHANNELS * 2;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&CHANNELS.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+/// The clips to send to a browser source.
+pub fn messages(audio: broadcast::Receiver<BrowserAudio>) -> impl Stream<Item = Message> {
+    futures::stream::unfold(audio, |mut audio| async move {
+        loop {
+            let message = match audio.recv().await {
+                Ok(BrowserAudio::Play(wav)) => Message::binary(wav.to_vec()),
+                Ok(BrowserAudio::Stop) => Message::text("stop"),
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("The browser source missed {} clip(s)", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            return Some((message, audio));
+        }
+    })
+}
@@ -0,0 +1,175 @@
+/// The author of a chat message, as described by its IRC tags.
+#[derive(Debug, Clone, Default)]
+pub struct Chatter {
+    /// The Twitch user ID, which stays the same when the user is renamed.
+    pub id: String,
+    /// The lowercase user name, which moderators use to refer to the chatter.
+    pub login: String,
+    pub display_name: String,
+    /// The amount of bits cheered in the message.
+    pub bits: u32,
+    /// Badge names without their versions, e.g. `subscriber` for `subscriber/12`.
+    pub badges: Vec<String>,
+}
+
+impl Chatter {
+    /// Build the chatter from a tag lookup, e.g. `|key| message.tags().get(key)`.
+    ///
+    /// Only notices have a `login` tag, for chat messages it has to be taken from the sender,
+    /// see `with_login`.
+    pub fn from_tags<'a>(tag: impl Fn(&str) -> Option<&'a str>) -> Chatter {
+        let display_name = tag("display-name").unwrap_or_default().to_string();
+        let login = tag("login")
+            .map(|login| login.to_string())
+            .unwrap_or_else(|| display_name.to_lowercase());
+        Chatter {
+            id: tag("user-id")
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| login.clone()),
+            login,
+            display_name,
+            bits: tag("bits")
+                .and_then(|bits| bits.parse().ok())
+                .unwrap_or_default(),
+            badges: tag("badges")
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|badge| badge.split('/').next())
+                .filter(|badge| !badge.is_empty())
+                .map(|badge| badge.to_string())
+                .collect(),
+        }
+    }
+
+    /// Display names can be localized, so the login comes from the IRC prefix when there is one.
+    pub fn with_login(mut self, login: Option<&str>) -> Chatter {
+        if let Some(login) = login.filter(|login| !login.is_empty()) {
+            self.login = login.to_lowercase();
+        }
+        self
+    }
+
+    pub fn has_badge(&self, name: &str) -> bool {
+        self.badges.iter().any(|badge| badge == name)
+    }
+
+    pub fn is_broadcaster(&self) -> bool {
+        self.has_badge("broadcaster")
+    }
+
+    /// Moderators and the broadcaster.
+    pub fn is_moderator(&self) -> bool {
+        self.is_broadcaster() || self.has_badge("moderator")
+    }
+}
+
+/// Remove cheermotes such as `Cheer100` or `Kappa5000` from a message.
+pub fn strip_cheermotes(text: &str) -> String {
+    text.split_whitespace()
+        .filter(|word| !is_cheermote(word))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The global cheermotes. Channel specific ones aren't known without the API, so they're read.
+const CHEERMOTE_PREFIXES: &[&str] = &[
+    "Cheer",
+    "DoodleCheer",
+    "BibleThump",
+    "cheerwhal",
+    "Corgo",
+    "Scoops",
+    "uni",
+    "ShowLove",
+    "Party",
+    "SeemsGood",
+    "Pride",
+    "Kappa",
+    "FrankerZ",
+    "HeyGuys",
+    "DansGame",
+    "EleGiggle",
+    "TriHard",
+    "Kreygasm",
+    "4Head",
+    "SwiftRage",
+    "NotLikeThis",
+    "FailFish",
+    "VoHiYo",
+    "PJSalt",
+    "MrDestructoid",
+    "bday",
+    "RIPCheer",
+    "Shamrock",
+    "BitBoss",
+    "Streamlabs",
+    "Muxy",
+    "HolidayCheer",
+    "Goal",
+    "Anon",
+    "Charity",
+];
+
+fn is_cheermote(word: &str) -> bool {
+    let prefix = word.trim_end_matches(|c: char| c.is_ascii_digit());
+    prefix.len() < word.len()
+        && CHEERMOTE_PREFIXES
+            .iter()
+            .any(|cheermote| cheermote.eq_ignore_ascii_case(prefix))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubKind {
+    Sub,
+    Resub,
+    Gift,
+    /// Several subs gifted to the community at once.
+    MysteryGift,
+}
+
+/// A subscription USERNOTICE.
+#[derive(Debug, Clone)]
+pub struct Subscription {
+    pub kind: SubKind,
+    pub user: String,
+    pub months: u32,
+    /// The user who received a gifted sub.
+    pub recipient: String,
+    /// The amount of subs gifted to the community.
+    pub count: u32,
+}
+
+impl Subscription {
+    /// Returns `None` for notices which aren't subscriptions (raids, rituals, etc.).
+    ///
+    /// A community gift is followed by a `subgift` notice for each recipient, which are skipped
+    /// so that the gift is only read once.
+    pub fn from_tags<'a>(tag: impl Fn(&str) -> Option<&'a str>) -> Option<Subscription> {
+        let kind = match tag("msg-id")? {
+            "sub" => SubKind::Sub,
+            "resub" => SubKind::Resub,
+            "subgift" | "anonsubgift" if tag("msg-param-community-gift-id").is_none() => {
+                SubKind::Gift
+            }
+            "submysterygift" | "anonsubmysterygift" => SubKind::MysteryGift,
+            _ => return None,
+        };
+        Some(Subscription {
+            kind,
+            // Anonymous gifts are sent by a placeholder user.
+            user: tag("display-name")
+                .filter(|name| !name.is_empty() && !name.eq_ignore_ascii_case("AnAnonymousGifter"))
+                .unwrap_or("An anonymous gifter")
+                .to_string(),
+            months: tag("msg-param-cumulative-months")
+                .and_then(|months| months.parse().ok())
+                .unwrap_or(1),
+            recipient: tag("msg-param-recipient-display-name")
+                .unwrap_or_default()
+                .to_string(),
+            count: tag("msg-param-mass-gift-count")
+                .and_then(|count| count.parse().ok())
+                .unwrap_or(1),
+        })
+    }
+}
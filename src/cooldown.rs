@@ -0,0 +1,135 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// The longest cooldown that can be configured, anything longer is rejected.
+pub const MAX_COOLDOWN_SECONDS: f64 = 24.0 * 60.0 * 60.0;
+
+/// Parsed from `ui::State::command_cooldown`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CooldownConfig {
+    /// How long a single user has to wait between requests.
+    pub user: Duration,
+    /// How long everyone has to wait after any request.
+    pub global: Duration,
+}
+
+impl CooldownConfig {
+    /// Cooldown syntax, in seconds:
+    /// ```
+    /// <user>[, <global>]
+    /// ```
+    pub fn parse(value: &str) -> Option<CooldownConfig> {
+        let value = value.trim();
+        if value.is_empty() {
+            return Some(CooldownConfig::default());
+        }
+
+        let (user, global) = match value.split_once(',') {
+            Some((user, global)) => (user.trim(), global.trim()),
+            None => (value, "0"),
+        };
+        Some(CooldownConfig {
+            user: parse_seconds(user)?,
+            global: parse_seconds(global)?,
+        })
+    }
+}
+
+/// `Duration::from_secs_f64` panics on values it can't represent, so those are rejected first.
+fn parse_seconds(value: &str) -> Option<Duration> {
+    let seconds = value.parse::<f64>().ok()?;
+    if !seconds.is_finite() || !(0.0..=MAX_COOLDOWN_SECONDS).contains(&seconds) {
+        return None;
+    }
+    Some(Duration::from_secs_f64(seconds))
+}
+
+/// A per-user command cooldown, plus an optional global one.
+#[derive(Default)]
+pub struct Cooldown {
+    config: CooldownConfig,
+    users: HashMap<String, Instant>,
+    global: Option<Instant>,
+}
+
+impl Cooldown {
+    pub fn configure(&mut self, config: CooldownConfig) {
+        self.config = config;
+    }
+
+    /// Start the cooldown for `user`, or return how long they still have to wait.
+    pub fn try_use(&mut self, user: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+
+        let remaining = [
+            self.global
+                .map(|last| self.config.global.checked_sub(now - last)),
+            self.users
+                .get(user)
+                .map(|last| self.config.user.checked_sub(now - *last)),
+        ]
+        .iter()
+        .flatten()
+        .flatten()
+        .filter(|remaining| !remaining.is_zero())
+        .max()
+        .copied();
+        if let Some(remaining) = remaining {
+            return Err(remaining);
+        }
+
+        // Forget the users whose cooldown has expired, so the map doesn't grow forever.
+        let user_cooldown = self.config.user;
+        self.users.retain(|_, last| now - *last < user_cooldown);
+
+        self.users.insert(user.to_string(), now);
+        self.global = Some(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_user_and_global_cooldowns() {
+        assert_eq!(CooldownConfig::parse(""), Some(CooldownConfig::default()));
+        assert_eq!(
+            CooldownConfig::parse("5, 10"),
+            Some(CooldownConfig {
+                user: Duration::from_secs(5),
+                global: Duration::from_secs(10),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_cooldowns_which_cant_be_represented() {
+        for value in &["inf", "NaN", "-1", "1e30", "5, inf", "abc"] {
+            assert_eq!(CooldownConfig::parse(value), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn user_cooldown_is_per_user_and_global_cooldown_is_shared() {
+        let mut cooldown = Cooldown::default();
+        cooldown.configure(CooldownConfig {
+            user: Duration::from_secs(60),
+            global: Duration::from_secs(0),
+        });
+        assert!(cooldown.try_use("a").is_ok());
+        assert!(cooldown.try_use("a").is_err());
+        assert!(cooldown.try_use("b").is_ok());
+
+        let mut cooldown = Cooldown::default();
+        cooldown.configure(CooldownConfig {
+            user: Duration::from_secs(0),
+            global: Duration::from_secs(60),
+        });
+        assert!(cooldown.try_use("a").is_ok());
+        assert!(cooldown.try_use("b").is_err());
+    }
+}
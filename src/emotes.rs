@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::SystemTime};
+
+/// What to do with the emotes in a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EmoteMode {
+    Remove,
+    /// Read the name of the emote, split into words, e.g. "Pog Champ".
+    SpeakName,
+    /// Play the sound effect with the same name as the emote, if there is one.
+    Sound,
+}
+
+impl Default for EmoteMode {
+    fn default() -> Self {
+        EmoteMode::Remove
+    }
+}
+
+impl EmoteMode {
+    pub const ALL: [EmoteMode; 3] = [EmoteMode::Remove, EmoteMode::SpeakName, EmoteMode::Sound];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmoteMode::Remove => "Remove them",
+            EmoteMode::SpeakName => "Read their names",
+            EmoteMode::Sound => "Play their sound effects",
+        }
+    }
+}
+
+/// A JSON array of emote names, for emotes which Twitch doesn't know about (BTTV, FFZ, 7TV).
+pub fn get_third_party_emotes_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("emotes.json");
+    path
+}
+
+/// The third-party emotes, as of the last `reload`.
+#[derive(Default)]
+pub struct ThirdPartyEmotes {
+    names: Vec<String>,
+    modified: Option<SystemTime>,
+}
+
+impl ThirdPartyEmotes {
+    pub fn get(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Read the file again if it changed since the last call.
+    pub fn reload(&mut self) {
+        let path = get_third_party_emotes_path();
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        if modified != self.modified {
+            self.modified = modified;
+            self.names = std::fs::read_to_string(&path)
+                .ok()
+                .and_then(|file| match serde_json::from_str(&file) {
+                    Ok(names) => Some(names),
+                    Err(e) => {
+                        log::error!("Failed to parse {}: {}", path.display(), e);
+                        None
+                    }
+                })
+                .unwrap_or_default();
+        }
+    }
+}
+
+/// Apply `mode` to the emotes in `text`.
+///
+/// `tag` is the `emotes` IRC tag, e.g. `25:0-4,12-16/1902:6-10`, where the ranges are
+/// inclusive character offsets into the message. `sounds` is only used in `EmoteMode::Sound`.
+pub fn clean(
+    text: &str,
+    tag: Option<&str>,
+    third_party: &[String],
+    sounds: &[String],
+    mode: EmoteMode,
+) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut ranges = tag
+        .unwrap_or_default()
+        .split('/')
+        .filter_map(|emote| emote.split_once(':'))
+        .flat_map(|(_, ranges)| ranges.split(','))
+        .filter_map(|range| {
+            let (start, end) = range.split_once('-')?;
+            Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+        })
+        .filter(|(start, end)| start <= end && *end < chars.len())
+        .collect::<Vec<_>>();
+    ranges.sort_unstable();
+
+    let replace = |name: &str| match mode {
+        EmoteMode::Remove => String::new(),
+        EmoteMode::SpeakName => split_words(name),
+        EmoteMode::Sound => {
+            let name = name.to_lowercase();
+            if sounds.contains(&name) {
+                format!("({})", name)
+            } else {
+                String::new()
+            }
+        }
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let mut next = 0;
+    for (start, end) in ranges {
+        if start < next {
+            continue;
+        }
+        out.extend(&chars[next..start]);
+        let name = chars[start..=end].iter().collect::<String>();
+        out.push_str(&replace(&name));
+        next = end + 1;
+    }
+    out.extend(&chars[next..]);
+
+    out.split_whitespace()
+        .map(|word| {
+            // Chatters often write third-party emotes next to punctuation, e.g. `KEKW!`.
+            let name = word.trim_matches(|c: char| c.is_ascii_punctuation());
+            if third_party.iter().any(|emote| emote == name) {
+                replace(name)
+            } else {
+                word.to_string()
+            }
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `PogChamp` -> `Pog Champ`, `LUL` -> `LUL`
+fn split_words(name: &str) -> String {
+    let mut words = String::with_capacity(name.len() + 4);
+    let mut previous: Option<char> = None;
+    for c in name.chars() {
+        if let Some(p) = previous {
+            if c.is_uppercase() && p.is_lowercase() {
+                words.push(' ');
+            }
+        }
+        words.push(c);
+        previous = Some(c);
+    }
+    words
+}
@@ -0,0 +1,107 @@
+use crate::{normalize, queue::Segment, tts::TtsRequest};
+use serde::{Deserialize, Serialize};
+
+/// What to do with requests which contain banned words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CensorPolicy {
+    Drop,
+    /// Replace each banned word with `ui::State::censor_substitute`.
+    Substitute,
+    /// Replace each banned word with a beep.
+    Beep,
+    /// Hold the request until the streamer approves it.
+    Approve,
+}
+
+impl Default for CensorPolicy {
+    fn default() -> Self {
+        CensorPolicy::Drop
+    }
+}
+
+impl CensorPolicy {
+    pub const ALL: [CensorPolicy; 4] = [
+        CensorPolicy::Drop,
+        CensorPolicy::Substitute,
+        CensorPolicy::Beep,
+        CensorPolicy::Approve,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CensorPolicy::Drop => "Drop the request",
+            CensorPolicy::Substitute => "Substitute banned words",
+            CensorPolicy::Beep => "Beep banned words",
+            CensorPolicy::Approve => "Hold for approval",
+        }
+    }
+}
+
+/// The character the censor replaces banned characters with.
+const CENSOR_CHAR: char = '*';
+
+/// Find the banned words in `text`, as indices into `text.split_whitespace()`.
+///
+/// `CENSOR_CHAR` never survives `normalize::normalize`, so any censored word that contains it
+/// is banned.
+pub fn find_banned_words(censor: &censor::Censor, text: &str) -> Vec<usize> {
+    debug_assert!(!normalize::is_kept(CENSOR_CHAR));
+    censor
+        .censor(text)
+        .split_whitespace()
+        .zip(text.split_whitespace())
+        .enumerate()
+        .filter(|(_, (censored, original))| {
+            censored.contains(CENSOR_CHAR) && !original.contains(CENSOR_CHAR)
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Replace each banned word in `request` with `substitute`.
+pub fn substitute(request: &TtsRequest, banned: &[usize], substitute: &str) -> TtsRequest {
+    let text = request
+        .text
+        .split_whitespace()
+        .enumerate()
+        .map(|(i, word)| {
+            if banned.contains(&i) {
+                substitute
+            } else {
+                word
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    TtsRequest {
+        text,
+        speaker: request.speaker.clone(),
+    }
+}
+
+/// Split `request` around its banned words, which are replaced with beeps.
+pub fn beep(request: &TtsRequest, banned: &[usize]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut words = Vec::new();
+    for (i, word) in request.text.split_whitespace().enumerate() {
+        if banned.contains(&i) {
+            if !words.is_empty() {
+                segments.push(Segment::Speech(TtsRequest {
+                    text: words.join(" "),
+                    speaker: request.speaker.clone(),
+                }));
+                words.clear();
+            }
+            segments.push(Segment::Beep);
+        } else {
+            words.push(word);
+        }
+    }
+    if !words.is_empty() {
+        segments.push(Segment::Speech(TtsRequest {
+            text: words.join(" "),
+            speaker: request.speaker.clone(),
+        }));
+    }
+    segments
+}
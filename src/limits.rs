@@ -0,0 +1,178 @@
+use crate::tts::TtsRequest;
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// The most speakers one message can switch between, so a message made of many tiny segments
+/// can't queue a synthesis request for each of them.
+pub const MAX_SEGMENTS: usize = 8;
+/// How long the fade-out is when a clip is cut off.
+pub const FADE_OUT_MILLISECONDS: u64 = 500;
+
+/// What to do with messages longer than `ui::State::max_message_length`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LengthPolicy {
+    /// Cut the message off at the last word which fits.
+    Truncate,
+    Reject,
+}
+
+impl Default for LengthPolicy {
+    fn default() -> Self {
+        LengthPolicy::Truncate
+    }
+}
+
+impl LengthPolicy {
+    pub const ALL: [LengthPolicy; 2] = [LengthPolicy::Truncate, LengthPolicy::Reject];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LengthPolicy::Truncate => "Truncate",
+            LengthPolicy::Reject => "Reject",
+        }
+    }
+}
+
+/// Apply `policy` to the text of `requests` if it has more than `max` characters in total,
+/// `0` means no limit. Segments which don't fit at all are dropped, as are the ones after
+/// `MAX_SEGMENTS`.
+///
+/// Returns `None` if the message is rejected.
+pub fn limit_length(
+    mut requests: Vec<TtsRequest>,
+    max: usize,
+    policy: LengthPolicy,
+) -> Option<Vec<TtsRequest>> {
+    if requests.len() > MAX_SEGMENTS {
+        if policy == LengthPolicy::Reject {
+            return None;
+        }
+        requests.truncate(MAX_SEGMENTS);
+    }
+
+    let total = requests
+        .iter()
+        .map(|request| request.text.chars().count())
+        .sum::<usize>();
+    if max == 0 || total <= max {
+        return Some(requests);
+    }
+    if policy == LengthPolicy::Reject {
+        return None;
+    }
+
+    let mut remaining = max;
+    let mut limited = Vec::new();
+    for request in requests {
+        if remaining == 0 {
+            break;
+        }
+        let length = request.text.chars().count();
+        let text = limit_text(&request.text, remaining, policy)?;
+        remaining = remaining.saturating_sub(length);
+        if !text.is_empty() {
+            limited.push(TtsRequest { text, ..request });
+        }
+    }
+    Some(limited)
+}
+
+fn limit_text(text: &str, max: usize, policy: LengthPolicy) -> Option<String> {
+    if max == 0 || text.chars().count() <= max {
+        return Some(text.to_string());
+    }
+    match policy {
+        LengthPolicy::Reject => None,
+        LengthPolicy::Truncate => {
+            let end = text.char_indices().nth(max).map_or(text.len(), |(i, _)| i);
+            let truncated = &text[..end];
+            // Don't read half of a word, unless the first word is already too long.
+            let truncated = match truncated.rfind(char::is_whitespace) {
+                Some(space) if !text[end..].starts_with(char::is_whitespace) => &truncated[..space],
+                _ => truncated,
+            };
+            Some(truncated.trim_end().to_string())
+        }
+    }
+}
+
+/// The playback time left for the segments of one queue item.
+#[derive(Clone)]
+pub struct ClipBudget(Arc<AtomicU64>);
+
+impl ClipBudget {
+    pub fn new(max: Duration) -> ClipBudget {
+        ClipBudget(Arc::new(AtomicU64::new(max.as_nanos() as u64)))
+    }
+
+    /// Stop `source` once the budget runs out, fading it out over the last `FADE_OUT_MILLISECONDS`.
+    pub fn limit<S>(&self, source: S) -> Limited<S>
+    where
+        S: Source<Item = f32>,
+    {
+        Limited {
+            source,
+            budget: self.clone(),
+        }
+    }
+}
+
+pub struct Limited<S> {
+    source: S,
+    budget: ClipBudget,
+}
+
+impl<S> Iterator for Limited<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let remaining = self.budget.0.load(Ordering::Relaxed);
+        if remaining == 0 {
+            return None;
+        }
+        let sample = self.source.next()?;
+        let step =
+            1_000_000_000 / (self.source.sample_rate() as u64 * self.source.channels() as u64);
+        self.budget
+            .0
+            .store(remaining.saturating_sub(step), Ordering::Relaxed);
+
+        let fade = Duration::from_millis(FADE_OUT_MILLISECONDS).as_nanos() as u64;
+        if remaining < fade {
+            Some(sample * remaining as f32 / fade as f32)
+        } else {
+            Some(sample)
+        }
+    }
+}
+
+impl<S> Source for Limited<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
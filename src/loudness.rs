@@ -0,0 +1,29 @@
+use rodio::{buffer::SamplesBuffer, Source};
+
+/// The RMS level that clips are normalized to.
+pub const TARGET_RMS: f32 = 0.1;
+/// Quiet clips are amplified at most this much, so silence doesn't turn into noise.
+pub const MAX_GAIN: f32 = 4.0;
+
+/// Scale `source` to `TARGET_RMS`, without letting its peak clip.
+pub fn normalize<S>(source: S) -> SamplesBuffer<f32>
+where
+    S: Source<Item = f32>,
+{
+    let channels = source.channels();
+    let sample_rate = source.sample_rate();
+    let mut samples = source.collect::<Vec<_>>();
+    if samples.is_empty() {
+        return SamplesBuffer::new(channels, sample_rate, samples);
+    }
+
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    let peak = samples.iter().fold(0f32, |peak, s| peak.max(s.abs()));
+    if rms > 0.0 {
+        let gain = (TARGET_RMS / rms).min(MAX_GAIN).min(1.0 / peak);
+        for sample in &mut samples {
+            *sample *= gain;
+        }
+    }
+    SamplesBuffer::new(channels, sample_rate, samples)
+}
@@ -1,10 +1,28 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod backend;
+mod browser_output;
+mod chat;
+mod cooldown;
+mod emotes;
+mod filter;
+mod limits;
+mod loudness;
+mod moderation;
 mod msg;
+mod normalize;
+mod output_device;
+mod overlay;
+mod pronunciation;
+mod pubsub;
+mod queue;
 mod server;
+mod sounds;
 mod speakers;
+mod template;
 mod tts;
 mod ui;
+mod word_lists;
 
 use std::{path::PathBuf, sync::Arc};
 
@@ -48,6 +66,10 @@ fn init_config_dir() {
     if !path.exists() {
         std::fs::create_dir(&path).unwrap();
     }
+    let sounds = sounds::get_sounds_dir_path();
+    if !sounds.exists() {
+        std::fs::create_dir(&sounds).unwrap();
+    }
 }
 
 fn init_panic_hook() {
@@ -138,26 +160,24 @@ fn main() -> Result<()> {
             .build()
             .expect("Failed to build runtime"),
     );
+
+    let (sink, output_device) = output_device::start(state.output_device.clone());
+    sink.pause(); // pause by default
+
+    let tts_context = Arc::new(tts::TtsContext::new(sink, output_device, msg_send.clone()));
     let server = std::thread::spawn({
         let rt = rt.clone();
+        let tts_context = tts_context.clone();
         move || {
             log::info!("Started the authentication thread.");
             rt.block_on(async {
                 tokio::select! {
-                    _ = server::start(msg_send) => {}
+                    _ = server::start(msg_send, tts_context) => {}
                     _ = stop_server_rx => {}
                 }
             })
         }
     });
-
-    // QQQ: how should we handle the absence of the default output device?
-    let (_stream, stream_handle) =
-        rodio::OutputStream::try_default().expect("Couldn't connect to the default output device");
-    let sink = rodio::Sink::try_new(&stream_handle).unwrap();
-    sink.pause(); // pause by default
-
-    let tts_context = Arc::new(tts::TtsContext::new(sink));
     let tts = tts::start_tts_thread(tts_context.clone(), rt.clone(), stop_tts_rx);
 
     ui::start(rt, tts_context, msg_recv, state);
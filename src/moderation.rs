@@ -0,0 +1,173 @@
+use anyhow::{anyhow, Result};
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_BAN_MINUTES: u64 = 10;
+/// One week.
+pub const MAX_BAN_MINUTES: u64 = 7 * 24 * 60;
+
+/// Moderator command syntax:
+/// ```
+/// <prefix> skip
+/// <prefix> pause
+/// <prefix> resume
+/// <prefix> clear
+/// <prefix> disable
+/// <prefix> enable
+/// <prefix> ban <user> [minutes]
+/// <prefix> unban <user>
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModCommand {
+    Skip,
+    Pause,
+    Resume,
+    Clear,
+    Disable,
+    Enable,
+    Ban { user: String, minutes: u64 },
+    Unban { user: String },
+}
+
+impl std::fmt::Display for ModCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModCommand::Skip => write!(f, "skipped the current clip"),
+            ModCommand::Pause => write!(f, "paused TTS"),
+            ModCommand::Resume => write!(f, "resumed TTS"),
+            ModCommand::Clear => write!(f, "cleared the queue"),
+            ModCommand::Disable => write!(f, "disabled TTS"),
+            ModCommand::Enable => write!(f, "enabled TTS"),
+            ModCommand::Ban { user, minutes } => {
+                write!(f, "blocked {} from TTS for {} minutes", user, minutes)
+            }
+            ModCommand::Unban { user } => write!(f, "unblocked {} from TTS", user),
+        }
+    }
+}
+
+/// Returns `None` if `message` isn't a moderator command, or an error if it's a malformed one.
+pub fn parse_mod_command(prefix: &str, message: &str) -> Option<Result<ModCommand>> {
+    let mut words = message
+        .trim()
+        .strip_prefix(prefix.trim())
+        .filter(|rest| rest.starts_with(char::is_whitespace))?
+        .split_whitespace();
+    let command = match words.next()?.to_lowercase().as_str() {
+        "skip" => ModCommand::Skip,
+        "pause" => ModCommand::Pause,
+        "resume" => ModCommand::Resume,
+        "clear" => ModCommand::Clear,
+        "disable" => ModCommand::Disable,
+        "enable" => ModCommand::Enable,
+        "ban" => ModCommand::Ban {
+            user: normalize_user(words.next()?),
+            minutes: match words.next() {
+                Some(minutes) => match parse_minutes(minutes) {
+                    Ok(minutes) => minutes,
+                    Err(e) => return Some(Err(e)),
+                },
+                None => DEFAULT_BAN_MINUTES,
+            },
+        },
+        "unban" => ModCommand::Unban {
+            user: normalize_user(words.next()?),
+        },
+        _ => return None,
+    };
+    if words.next().is_some() {
+        return None;
+    }
+    Some(Ok(command))
+}
+
+fn parse_minutes(minutes: &str) -> Result<u64> {
+    match minutes.parse() {
+        Ok(minutes) if (1..=MAX_BAN_MINUTES).contains(&minutes) => Ok(minutes),
+        _ => Err(anyhow!(
+            "the ban duration must be between 1 and {} minutes, not {:?}",
+            MAX_BAN_MINUTES,
+            minutes
+        )),
+    }
+}
+
+fn normalize_user(user: &str) -> String {
+    user.trim_start_matches('@').to_lowercase()
+}
+
+/// Users who are temporarily blocked from TTS.
+///
+/// Moderators name users by login, but blocks are keyed on the user ID when it's known so that
+/// renaming doesn't lift them.
+#[derive(Default)]
+pub struct Blocklist {
+    users: HashMap<String, Instant>,
+    /// Login to user ID key, for the users seen so far. IDs are prefixed so that they can't clash
+    /// with numeric logins.
+    ids: HashMap<String, String>,
+}
+
+impl Blocklist {
+    /// Remember the ID of `login` so that blocking it covers future renames.
+    pub fn saw(&mut self, login: &str, id: &str) {
+        if !id.is_empty() {
+            self.ids.insert(normalize_user(login), format!("id:{}", id));
+        }
+    }
+
+    pub fn block(&mut self, user: &str, duration: Duration) -> Result<()> {
+        let until = Instant::now()
+            .checked_add(duration)
+            .ok_or_else(|| anyhow!("the ban duration is too long"))?;
+        self.users.insert(self.key(user), until);
+        Ok(())
+    }
+
+    pub fn unblock(&mut self, user: &str) {
+        let key = self.key(user);
+        self.users.remove(&key);
+        self.users.remove(&normalize_user(user));
+    }
+
+    /// `id` may be empty if it's unknown.
+    pub fn is_blocked(&mut self, login: &str, id: &str) -> bool {
+        let now = Instant::now();
+        self.users.retain(|_, until| *until > now);
+        (!id.is_empty() && self.users.contains_key(&format!("id:{}", id)))
+            || self.users.contains_key(&normalize_user(login))
+    }
+
+    fn key(&self, user: &str) -> String {
+        let login = normalize_user(user);
+        self.ids.get(&login).cloned().unwrap_or(login)
+    }
+}
+
+pub fn get_audit_log_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("audit.log");
+    path
+}
+
+/// Record a moderator action in the audit log.
+pub fn audit(moderator: &str, command: &ModCommand) {
+    let entry = format!(
+        "{} {} {}\n",
+        chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+        moderator,
+        command
+    );
+    let result = std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(get_audit_log_path())
+        .and_then(|mut file| file.write_all(entry.as_bytes()));
+    if let Err(e) = result {
+        log::error!("Failed to write to the audit log: {}", e);
+    }
+}
@@ -1,11 +1,32 @@
 pub enum Message {
-    Auth { token: String },
-    Error { what: String },
+    Auth {
+        token: String,
+    },
+    Error {
+        what: String,
+    },
+    /// A moderator used a chat command.
+    Moderation {
+        moderator: String,
+        action: String,
+    },
+    /// TTS was enabled or disabled from chat.
+    TtsEnabled(bool),
+    /// A TTS request contained banned words.
+    Censored {
+        user: String,
+        decision: String,
+    },
+    /// A TTS request was ignored.
+    Dropped {
+        user: String,
+        reason: String,
+    },
 }
 
 pub type Sender = crossbeam_channel::Sender<Message>;
 pub type Receiver = crossbeam_channel::Receiver<Message>;
 
 pub fn channel() -> (Sender, Receiver) {
-    crossbeam_channel::bounded(1)
+    crossbeam_channel::unbounded()
 }
@@ -0,0 +1,292 @@
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+
+/// Numbers with more digits than this are read digit by digit.
+pub const MAX_NUMBER_DIGITS: usize = 12;
+
+lazy_static::lazy_static! {
+    static ref URL: Regex = Regex::new(r"(?i)\b(?:https?://|www\.)\S+").unwrap();
+    static ref CURRENCY_BEFORE: Regex = Regex::new(r"([$€£])\s?(\d+(?:\.\d+)?)").unwrap();
+    static ref CURRENCY_AFTER: Regex = Regex::new(r"(\d+(?:\.\d+)?)\s?([$€£])").unwrap();
+    static ref NUMBER: Regex = Regex::new(r"\d+(?:\.\d+)?").unwrap();
+    static ref WORD: Regex = Regex::new(r"[A-Za-z]+").unwrap();
+
+    /// Chat abbreviations, and how to say them.
+    static ref ABBREVIATIONS: HashMap<&'static str, &'static str> = {
+        let mut abbreviations = HashMap::new();
+        abbreviations.insert("afk", "away from keyboard");
+        abbreviations.insert("brb", "be right back");
+        abbreviations.insert("btw", "by the way");
+        abbreviations.insert("gg", "good game");
+        abbreviations.insert("idk", "I don't know");
+        abbreviations.insert("imo", "in my opinion");
+        abbreviations.insert("irl", "in real life");
+        abbreviations.insert("lmao", "haha");
+        abbreviations.insert("lol", "laughing out loud");
+        abbreviations.insert("np", "no problem");
+        abbreviations.insert("omg", "oh my god");
+        abbreviations.insert("pls", "please");
+        abbreviations.insert("plz", "please");
+        abbreviations.insert("tbh", "to be honest");
+        abbreviations.insert("ty", "thank you");
+        abbreviations.insert("u", "you");
+        abbreviations.insert("ur", "your");
+        abbreviations
+    };
+}
+
+/// Words after which "u" is the letter, e.g. "plan u".
+const LETTER_WORDS: [&str; 4] = ["plan", "vitamin", "letter", "grade"];
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const SCALES: [(u64, &str); 3] = [
+    (1_000_000_000, "billion"),
+    (1_000_000, "million"),
+    (1_000, "thousand"),
+];
+
+/// Turn a chat message into something the voices can read.
+///
+/// Unicode letters are transliterated to ASCII and emoji are dropped, URLs, currency, numbers
+/// and chat abbreviations are spelled out, and long runs of the same character are collapsed
+/// ("lmaooooo"). Whatever is left that the voices can't pronounce is removed.
+pub fn normalize(text: &str) -> String {
+    let text = URL.replace_all(text, " link ");
+    let text = CURRENCY_BEFORE.replace_all(&text, |c: &Captures| currency(&c[2], &c[1]));
+    let text = CURRENCY_AFTER.replace_all(&text, |c: &Captures| currency(&c[1], &c[2]));
+    let text = transliterate(&text).replace('%', " percent");
+    let text = collapse_repeats(&text);
+    let text = NUMBER.replace_all(&text, |c: &Captures| format!(" {} ", number(&c[0])));
+    let text = WORD.replace_all(&text, |c: &Captures| {
+        let word = c.get(0).unwrap();
+        if word.as_str().eq_ignore_ascii_case("u") && !is_pronoun_u(&text, word.start()) {
+            return word.as_str().to_string();
+        }
+        let word = word.as_str();
+        ABBREVIATIONS
+            .get(word.to_lowercase().as_str())
+            .map_or_else(|| word.to_string(), |expanded| expanded.to_string())
+    });
+    strip_unpronounceable(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Transliterate letters to ASCII. Emoji and other symbols would be read by their names
+/// ("😂😂😂" -> "joy joy joy"), so they're dropped instead.
+fn transliterate(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() {
+            out.push(c);
+        } else if c.is_whitespace() {
+            out.push(' ');
+        } else if c.is_alphanumeric() {
+            out.push_str(deunicode::deunicode_char(c).unwrap_or_default());
+        }
+    }
+    out
+}
+
+/// Whether the "u" at `start` stands for "you": lowercase, on its own rather than part of
+/// "u-turn", and not a letter as in "plan u". "U" is more likely to be a name or an initial.
+fn is_pronoun_u(text: &str, start: usize) -> bool {
+    let (before, rest) = text.split_at(start);
+    let after = &rest[1..];
+    let previous_word = before.split_whitespace().last().unwrap_or_default();
+    rest.starts_with('u')
+        && before.chars().next_back().map_or(true, char::is_whitespace)
+        && after.chars().next().map_or(true, |c| {
+            c.is_whitespace() || [',', '.', '!', '?'].contains(&c)
+        })
+        && !LETTER_WORDS
+            .iter()
+            .any(|letter| letter.eq_ignore_ascii_case(previous_word))
+}
+
+/// The punctuation which is kept. Parentheses are kept for sound effects, see
+/// `sounds::insert_sounds`.
+const KEPT_PUNCTUATION: [char; 7] = [',', '.', '!', '?', '\'', '(', ')'];
+
+/// Whether `c` survives `normalize`.
+pub fn is_kept(c: char) -> bool {
+    c.is_ascii_digit() || c.is_ascii_alphabetic() || c == ' ' || KEPT_PUNCTUATION.contains(&c)
+}
+
+/// Remove everything the voices can't pronounce.
+fn strip_unpronounceable(text: &str) -> String {
+    text.chars()
+        .map(|c| if c.is_ascii_whitespace() { ' ' } else { c })
+        .filter(|c| is_kept(*c))
+        .collect()
+}
+
+/// Runs of three or more of the same character become one, except for digits.
+fn collapse_repeats(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        let run = chars[i..].iter().take_while(|c| **c == chars[i]).count();
+        let keep = if run >= 3 && !chars[i].is_ascii_digit() {
+            1
+        } else {
+            run
+        };
+        out.extend(std::iter::repeat(chars[i]).take(keep));
+        i += run;
+    }
+    out
+}
+
+fn currency(amount: &str, symbol: &str) -> String {
+    let unit = match symbol {
+        "$" => "dollar",
+        "€" => "euro",
+        _ => "pound",
+    };
+    let plural = if amount == "1" { "" } else { "s" };
+    format!(" {} {}{} ", amount, unit, plural)
+}
+
+/// `12.5` -> "twelve point five"
+fn number(digits: &str) -> String {
+    let (whole, fraction) = match digits.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (digits, None),
+    };
+    let mut words = match whole.parse::<u64>() {
+        Ok(n) if whole.len() <= MAX_NUMBER_DIGITS => integer(n),
+        _ => spell_digits(whole),
+    };
+    if let Some(fraction) = fraction {
+        words.push_str(" point ");
+        words.push_str(&spell_digits(fraction));
+    }
+    words
+}
+
+fn spell_digits(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| ONES[d as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn integer(n: u64) -> String {
+    for (scale, name) in SCALES.iter() {
+        if n >= *scale {
+            let rest = n % scale;
+            let head = format!("{} {}", integer(n / scale), name);
+            return if rest == 0 {
+                head
+            } else {
+                format!("{} {}", head, integer(rest))
+            };
+        }
+    }
+    if n >= 100 {
+        let rest = n % 100;
+        let head = format!("{} hundred", ONES[(n / 100) as usize]);
+        return if rest == 0 {
+            head
+        } else {
+            format!("{} {}", head, integer(rest))
+        };
+    }
+    if n >= 20 {
+        let rest = n % 10;
+        return if rest == 0 {
+            TENS[(n / 10) as usize].to_string()
+        } else {
+            format!("{} {}", TENS[(n / 10) as usize], ONES[rest as usize])
+        };
+    }
+    ONES[n as usize].to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_emoji() {
+        assert_eq!(normalize("gg 😂😂😂"), "good game");
+    }
+
+    #[test]
+    fn transliterates_accented_letters() {
+        assert_eq!(normalize("café naïve"), "cafe naive");
+    }
+
+    #[test]
+    fn spells_out_currency() {
+        assert_eq!(normalize("$5"), "five dollars");
+        assert_eq!(normalize("5€"), "five euros");
+        assert_eq!(normalize("$1"), "one dollar");
+    }
+
+    #[test]
+    fn spells_out_numbers() {
+        assert_eq!(normalize("12.5"), "twelve point five");
+        assert_eq!(normalize("1000001"), "one million one");
+        assert_eq!(
+            normalize("1234567890123"),
+            "one two three four five six seven eight nine zero one two three"
+        );
+    }
+
+    #[test]
+    fn replaces_urls() {
+        assert_eq!(
+            normalize("look https://example.com/a?b=c now"),
+            "look link now"
+        );
+    }
+
+    #[test]
+    fn collapses_repeated_characters() {
+        assert_eq!(normalize("nooooo"), "no");
+        // Collapsed before the abbreviations are expanded.
+        assert_eq!(normalize("lmaooooo"), "haha");
+    }
+
+    #[test]
+    fn only_expands_a_standalone_lowercase_u() {
+        assert_eq!(normalize("love u"), "love you");
+        assert_eq!(normalize("plan u"), "plan u");
+        assert_eq!(normalize("U know"), "U know");
+    }
+
+    #[test]
+    fn unpronounceable_text_becomes_empty() {
+        assert_eq!(normalize("😂 ~~ @#"), "");
+    }
+}
@@ -0,0 +1,173 @@
+use rodio::{
+    cpal::traits::{DeviceTrait, HostTrait},
+    queue::SourcesQueueOutput,
+    Source,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::Duration,
+};
+
+/// How much audio is discarded at a time when there is no output device.
+pub const NULL_SINK_TICK_MILLISECONDS: u64 = 10;
+
+/// The names of the output devices, for the UI.
+pub fn list() -> Vec<String> {
+    match rodio::cpal::default_host().output_devices() {
+        Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+        Err(e) => {
+            log::error!("Failed to list the output devices: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Plays a sink on the selected output device, and moves it when another one is selected.
+pub struct OutputDevice {
+    selected: Mutex<Option<String>>,
+    select_tx: Mutex<mpsc::Sender<Option<String>>>,
+}
+
+impl OutputDevice {
+    /// Switch to the device named `name`, or the default device if it's `None`.
+    pub fn select(&self, name: Option<String>) {
+        let mut selected = self.selected.lock().unwrap();
+        if *selected != name {
+            *selected = name.clone();
+            let _ = self.select_tx.lock().unwrap().send(name);
+        }
+    }
+}
+
+/// Create the sink and start playing it on `device`.
+///
+/// `rodio::OutputStream` can't be sent between threads, so the streams are owned by
+/// a separate thread. If a device can't be opened, the sink is still played, but nothing
+/// is heard, so the queue keeps moving.
+pub fn start(device: Option<String>) -> (rodio::Sink, OutputDevice) {
+    let (sink, output) = rodio::Sink::new_idle();
+    let output = Arc::new(Mutex::new(output));
+    let (select_tx, select_rx) = mpsc::channel();
+    let _ = select_tx.send(device.clone());
+
+    std::thread::spawn(move || {
+        let mut current: Option<(rodio::OutputStream, Arc<AtomicBool>)> = None;
+        let mut next = select_rx.recv().ok();
+        while let Some(device) = next.take() {
+            if let Some((_, active)) = current.take() {
+                active.store(false, Ordering::SeqCst);
+            }
+            current = open(device.as_deref()).and_then(|(stream, handle)| {
+                let active = Arc::new(AtomicBool::new(true));
+                let source = SharedOutput {
+                    output: output.clone(),
+                    active: active.clone(),
+                };
+                match handle.play_raw(source) {
+                    Ok(()) => Some((stream, active)),
+                    Err(e) => {
+                        log::error!("Failed to play on the output device: {}", e);
+                        None
+                    }
+                }
+            });
+
+            next = if current.is_some() {
+                select_rx.recv().ok()
+            } else {
+                log::warn!("No output device, the TTS can only be heard in the browser source");
+                loop {
+                    let tick = Duration::from_millis(NULL_SINK_TICK_MILLISECONDS);
+                    match select_rx.recv_timeout(tick) {
+                        Ok(device) => break Some(device),
+                        Err(mpsc::RecvTimeoutError::Timeout) => discard(&output, tick),
+                        Err(mpsc::RecvTimeoutError::Disconnected) => break None,
+                    }
+                }
+            };
+        }
+    });
+
+    let device = OutputDevice {
+        selected: Mutex::new(device),
+        select_tx: Mutex::new(select_tx),
+    };
+    (sink, device)
+}
+
+fn open(name: Option<&str>) -> Option<(rodio::OutputStream, rodio::OutputStreamHandle)> {
+    let result = match name {
+        None => rodio::OutputStream::try_default(),
+        Some(name) => {
+            let device = rodio::cpal::default_host()
+                .output_devices()
+                .ok()?
+                .find(|device| device.name().ok().as_deref() == Some(name));
+            match device {
+                Some(device) => rodio::OutputStream::try_from_device(&device),
+                None => {
+                    log::error!("The output device {} doesn't exist", name);
+                    return None;
+                }
+            }
+        }
+    };
+    match result {
+        Ok(stream) => {
+            log::info!("Playing on {}", name.unwrap_or("the default output device"));
+            Some(stream)
+        }
+        Err(e) => {
+            log::error!("Couldn't connect to the output device: {}", e);
+            None
+        }
+    }
+}
+
+/// Consume `duration` worth of the sink's audio, as if it was played.
+fn discard(output: &Mutex<SourcesQueueOutput<f32>>, duration: Duration) {
+    let mut output = output.lock().unwrap();
+    let samples =
+        output.sample_rate() as u128 * output.channels() as u128 * duration.as_millis() / 1000;
+    for _ in 0..samples {
+        output.next();
+    }
+}
+
+/// The sink's audio, which stops on the old device once another one is selected.
+struct SharedOutput {
+    output: Arc<Mutex<SourcesQueueOutput<f32>>>,
+    active: Arc<AtomicBool>,
+}
+
+impl Iterator for SharedOutput {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if !self.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        self.output.lock().unwrap().next()
+    }
+}
+
+impl Source for SharedOutput {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.output.lock().unwrap().current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.output.lock().unwrap().channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.output.lock().unwrap().sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
@@ -0,0 +1,62 @@
+use futures::Stream;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+use warp::ws::{Message, WebSocket};
+
+/// What the overlay shows while an item is playing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NowPlaying {
+    pub user: String,
+    pub speaker: String,
+    pub text: String,
+}
+
+pub fn get_overlay_html_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("overlay.html");
+    path
+}
+
+pub fn get_overlay_css_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("overlay.css");
+    path
+}
+
+/// Read an overlay file, creating it with the default content if it doesn't exist yet.
+///
+/// The files are read on every request, so edits show up after refreshing the browser source.
+fn load(path: &Path, default: &str) -> String {
+    if !path.exists() {
+        if let Err(e) = std::fs::write(path, default) {
+            log::error!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+    std::fs::read_to_string(path).unwrap_or_else(|_| default.to_string())
+}
+
+pub fn html() -> String {
+    load(&get_overlay_html_path(), include_str!("./overlay.html"))
+}
+
+pub fn css() -> String {
+    load(&get_overlay_css_path(), include_str!("./overlay.css"))
+}
+
+/// The current item, and then every change to it. `null` means nothing is playing.
+pub fn updates(now_playing: watch::Receiver<Option<NowPlaying>>) -> impl Stream<Item = Message> {
+    futures::stream::unfold((now_playing, true), |(mut now_playing, first)| async move {
+        if !first && now_playing.changed().await.is_err() {
+            return None;
+        }
+        let json = serde_json::to_string(&*now_playing.borrow());
+        match json {
+            Ok(json) => Some((Message::text(json), (now_playing, false))),
+            Err(e) => {
+                log::error!("Failed to serialize the overlay state: {}", e);
+                None
+            }
+        }
+    })
+}
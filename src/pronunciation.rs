@@ -0,0 +1,90 @@
+use regex::{NoExpand, Regex};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// How to pronounce a word, e.g. `xqc` -> `ex cue see`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pronunciation {
+    /// A word, matched case-insensitively, or a regular expression if `regex` is set.
+    pub pattern: String,
+    /// The replacement, which can refer to regex groups with `$1`.
+    pub respelling: String,
+    #[serde(default)]
+    pub regex: bool,
+}
+
+pub fn get_pronunciations_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("pronunciations.json");
+    path
+}
+
+pub fn load() -> Vec<Pronunciation> {
+    std::fs::read_to_string(get_pronunciations_path())
+        .ok()
+        .and_then(|file| match serde_json::from_str(&file) {
+            Ok(entries) => Some(entries),
+            Err(e) => {
+                log::error!("Failed to parse the pronunciation dictionary: {}", e);
+                None
+            }
+        })
+        .unwrap_or_default()
+}
+
+pub fn save(entries: &[Pronunciation]) {
+    match serde_json::to_string_pretty(entries) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(get_pronunciations_path(), json) {
+                log::error!("Failed to save the pronunciation dictionary: {}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to serialize the pronunciation dictionary: {}", e),
+    }
+}
+
+/// The compiled entries, applied in order.
+#[derive(Default)]
+pub struct Dictionary {
+    /// The pattern, the respelling, and whether `$` in the respelling refers to capture groups.
+    rules: Vec<(Regex, String, bool)>,
+}
+
+impl Dictionary {
+    /// Entries with an invalid regex are skipped.
+    pub fn new(entries: &[Pronunciation]) -> Dictionary {
+        let rules = entries
+            .iter()
+            .filter(|entry| !entry.pattern.trim().is_empty())
+            .filter_map(|entry| {
+                let pattern = if entry.regex {
+                    entry.pattern.clone()
+                } else {
+                    format!(r"(?i)\b{}\b", regex::escape(entry.pattern.trim()))
+                };
+                match Regex::new(&pattern) {
+                    Ok(regex) => Some((regex, entry.respelling.clone(), entry.regex)),
+                    Err(e) => {
+                        log::error!("Invalid pronunciation pattern `{}`: {}", entry.pattern, e);
+                        None
+                    }
+                }
+            })
+            .collect();
+        Dictionary { rules }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (regex, respelling, expand) in &self.rules {
+            text = if *expand {
+                regex.replace_all(&text, respelling.as_str())
+            } else {
+                // A plain respelling like `$5 bill` is said as written.
+                regex.replace_all(&text, NoExpand(respelling))
+            }
+            .into_owned();
+        }
+        text
+    }
+}
@@ -0,0 +1,324 @@
+use anyhow::{anyhow, Result};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+pub const PUBSUB_URL: &str = "wss://pubsub-edge.twitch.tv";
+pub const VALIDATE_URL: &str = "https://id.twitch.tv/oauth2/validate";
+/// Twitch disconnects clients which don't send a PING at least once every 5 minutes.
+pub const PING_INTERVAL_SECONDS: u64 = 4 * 60;
+pub const RECONNECT_DELAY_SECONDS: u64 = 5;
+pub const MAX_RECONNECT_DELAY_SECONDS: u64 = 120;
+
+/// A channel point reward redemption.
+#[derive(Debug, Clone)]
+pub struct Redemption {
+    pub user_id: String,
+    pub user_login: String,
+    pub user_name: String,
+    pub reward_id: String,
+    pub reward_title: String,
+    /// The text entered by the user, if the reward requires it.
+    pub user_input: String,
+}
+
+impl Redemption {
+    /// `reward` may be either the ID or the title of the reward.
+    pub fn is_reward(&self, reward: &str) -> bool {
+        let reward = reward.trim();
+        !reward.is_empty()
+            && (self.reward_id == reward || self.reward_title.eq_ignore_ascii_case(reward))
+    }
+}
+
+#[derive(Deserialize)]
+struct Validation {
+    user_id: String,
+}
+
+/// Get the ID of the user who authorized `token`, which is the broadcaster.
+pub async fn get_channel_id(
+    client: &reqwest::Client,
+    validate_url: &str,
+    token: &str,
+) -> Result<String> {
+    let response = client
+        .get(validate_url)
+        .header(reqwest::header::AUTHORIZATION, format!("OAuth {}", token))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Failed to validate the token: HTTP {}",
+            response.status()
+        ));
+    }
+    Ok(response.json::<Validation>().await?.user_id)
+}
+
+/// Listen to redemptions until `redemptions` is closed, reconnecting whenever the connection fails.
+///
+/// `url` and `validate_url` are configurable so that a local stand-in server can be used instead
+/// of Twitch.
+pub async fn run(
+    client: reqwest::Client,
+    url: String,
+    validate_url: String,
+    token: String,
+    redemptions: mpsc::Sender<Redemption>,
+) {
+    let mut delay = RECONNECT_DELAY_SECONDS;
+    while !redemptions.is_closed() {
+        let result = match get_channel_id(&client, &validate_url, &token).await {
+            Ok(channel_id) => listen(&url, &token, &channel_id, &redemptions).await,
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {
+                log::info!("PubSub asked us to reconnect");
+                delay = RECONNECT_DELAY_SECONDS;
+            }
+            Err(e) => {
+                log::error!(
+                    "PubSub connection failed: {}. Reconnecting in {}s",
+                    e,
+                    delay
+                );
+                tokio::time::sleep(Duration::from_secs(delay)).await;
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY_SECONDS);
+            }
+        }
+    }
+}
+
+/// Connect to PubSub and forward the redemptions in `channel_id`.
+///
+/// Returns `Ok` if the server asked us to reconnect.
+pub async fn listen(
+    url: &str,
+    token: &str,
+    channel_id: &str,
+    redemptions: &mpsc::Sender<Redemption>,
+) -> Result<()> {
+    let (mut ws, _) = tokio_tungstenite::connect_async(url).await?;
+    log::info!("Connected to PubSub at {}", url);
+
+    ws.send(Message::Text(
+        json!({
+            "type": "LISTEN",
+            "nonce": "obs-tts",
+            "data": {
+                "topics": [format!("channel-points-channel-v1.{}", channel_id)],
+                "auth_token": token,
+            }
+        })
+        .to_string(),
+    ))
+    .await?;
+
+    let mut ping = tokio::time::interval(Duration::from_secs(PING_INTERVAL_SECONDS));
+    loop {
+        tokio::select! {
+            _ = ping.tick() => {
+                ws.send(Message::Text(json!({ "type": "PING" }).to_string())).await?;
+            }
+            message = ws.next() => match message {
+                Some(Ok(Message::Text(text))) => match parse_message(&text) {
+                    Ok(Event::Redemption(redemption)) => {
+                        log::info!("Received a redemption: {:#?}", redemption);
+                        if redemptions.send(redemption).await.is_err() {
+                            return Ok(());
+                        }
+                    }
+                    Ok(Event::Reconnect) => return Ok(()),
+                    Ok(Event::ListenFailed(error)) => {
+                        return Err(anyhow!("Failed to listen to redemptions: {}", error))
+                    }
+                    Ok(Event::Other) => {}
+                    // One bad frame shouldn't cost us the redemptions which follow it.
+                    Err(e) => log::warn!("Ignoring a malformed PubSub frame ({}): {}", e, text),
+                },
+                Some(Ok(Message::Close(frame))) => return Err(anyhow!("Connection closed: {:?}", frame)),
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e.into()),
+                None => return Err(anyhow!("Connection closed")),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Event {
+    Redemption(Redemption),
+    Reconnect,
+    ListenFailed(String),
+    Other,
+}
+
+#[derive(Deserialize)]
+struct Frame {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    data: Option<FrameData>,
+}
+
+#[derive(Deserialize)]
+struct FrameData {
+    /// JSON encoded `TopicMessage`.
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct TopicMessage {
+    #[serde(rename = "type")]
+    kind: String,
+    data: TopicData,
+}
+
+#[derive(Deserialize)]
+struct TopicData {
+    redemption: RedemptionData,
+}
+
+#[derive(Deserialize)]
+struct RedemptionData {
+    user: UserData,
+    reward: RewardData,
+    #[serde(default)]
+    user_input: String,
+}
+
+#[derive(Deserialize)]
+struct UserData {
+    id: String,
+    login: String,
+    display_name: String,
+}
+
+#[derive(Deserialize)]
+struct RewardData {
+    id: String,
+    title: String,
+}
+
+fn parse_message(text: &str) -> Result<Event> {
+    let frame: Frame = serde_json::from_str(text)?;
+    match frame.kind.as_str() {
+        "RESPONSE" => match frame.error.filter(|e| !e.is_empty()) {
+            Some(error) => Ok(Event::ListenFailed(error)),
+            None => Ok(Event::Other),
+        },
+        "RECONNECT" => Ok(Event::Reconnect),
+        "MESSAGE" => {
+            let data = frame
+                .data
+                .ok_or_else(|| anyhow!("Received a MESSAGE without data"))?;
+            let message: TopicMessage = match serde_json::from_str(&data.message) {
+                Ok(message) => message,
+                Err(e) => {
+                    log::debug!(
+                        "Ignoring an unknown PubSub message ({}): {}",
+                        e,
+                        data.message
+                    );
+                    return Ok(Event::Other);
+                }
+            };
+            if message.kind != "reward-redeemed" {
+                return Ok(Event::Other);
+            }
+            let redemption = message.data.redemption;
+            Ok(Event::Redemption(Redemption {
+                user_id: redemption.user.id,
+                user_login: redemption.user.login,
+                user_name: redemption.user.display_name,
+                reward_id: redemption.reward.id,
+                reward_title: redemption.reward.title,
+                user_input: redemption.user_input,
+            }))
+        }
+        _ => Ok(Event::Other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A redemption as sent by Twitch, from the PubSub documentation.
+    const REDEMPTION: &str = r##"{
+        "type": "reward-redeemed",
+        "data": {
+            "timestamp": "2019-11-12T01:29:34.98329743Z",
+            "redemption": {
+                "id": "9203c6f0-51b6-4d1d-a9ae-8eafdb0d6d47",
+                "user": {
+                    "id": "30515034",
+                    "login": "davethecust",
+                    "display_name": "davethecust"
+                },
+                "channel_id": "30515034",
+                "redeemed_at": "2019-12-11T18:52:53.128421623Z",
+                "reward": {
+                    "id": "6ef17bb2-e5ae-432e-8b3f-5ac4dd774668",
+                    "channel_id": "30515034",
+                    "title": "hit a gleesh walk on stream",
+                    "prompt": "cleanside's finest \n",
+                    "cost": 10,
+                    "is_user_input_required": true,
+                    "is_sub_only": false,
+                    "image": null,
+                    "default_image": {
+                        "url_1x": "https://static-cdn.jtvnw.net/custom-reward-images/default-1.png",
+                        "url_2x": "https://static-cdn.jtvnw.net/custom-reward-images/default-2.png",
+                        "url_4x": "https://static-cdn.jtvnw.net/custom-reward-images/default-4.png"
+                    },
+                    "background_color": "#00C7AC",
+                    "is_enabled": true,
+                    "is_paused": false,
+                    "is_in_stock": true,
+                    "max_per_stream": { "is_enabled": false, "max_per_stream": 0 },
+                    "should_redemptions_skip_request_queue": true
+                },
+                "user_input": "yeooo",
+                "status": "FULFILLED"
+            }
+        }
+    }"##;
+
+    #[test]
+    fn parses_a_redemption() {
+        let frame = json!({
+            "type": "MESSAGE",
+            "data": {
+                "topic": "channel-points-channel-v1.30515034",
+                "message": REDEMPTION,
+            }
+        })
+        .to_string();
+
+        match parse_message(&frame).unwrap() {
+            Event::Redemption(redemption) => {
+                assert_eq!(redemption.user_id, "30515034");
+                assert_eq!(redemption.user_login, "davethecust");
+                assert_eq!(redemption.user_name, "davethecust");
+                assert_eq!(redemption.reward_id, "6ef17bb2-e5ae-432e-8b3f-5ac4dd774668");
+                assert_eq!(redemption.reward_title, "hit a gleesh walk on stream");
+                assert_eq!(redemption.user_input, "yeooo");
+            }
+            event => panic!("Expected a redemption, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn malformed_frames_are_errors() {
+        assert!(parse_message("not json").is_err());
+        assert!(parse_message(r#"{"type": "MESSAGE"}"#).is_err());
+    }
+}
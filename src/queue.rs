@@ -0,0 +1,221 @@
+use crate::{backend::Audio, tts::TtsRequest};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Status {
+    /// Waiting for the streamer to approve or reject it.
+    AwaitingApproval,
+    Pending,
+    /// Waiting for the rate limiter or the backend.
+    Synthesizing,
+    /// Synthesized and waiting for its turn.
+    Ready,
+    Playing,
+    Failed,
+}
+
+impl Status {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Status::AwaitingApproval => "✋ needs approval",
+            Status::Pending => "⏳ pending",
+            Status::Synthesizing => "⚙ synthesizing",
+            Status::Ready => "✔ ready",
+            Status::Playing => "🔊 playing",
+            Status::Failed => "❌ failed",
+        }
+    }
+}
+
+/// A part of a queue item. The segments of an item are played back to back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Segment {
+    Speech(TtsRequest),
+    /// Covers a banned word.
+    Beep,
+    /// A sound effect from the sounds folder.
+    Sound(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueueItem {
+    pub id: u64,
+    /// The display name of the user who requested the item.
+    pub requester: String,
+    pub segments: Vec<Segment>,
+    pub status: Status,
+    /// Why the item failed, if it did.
+    #[serde(skip)]
+    pub error: Option<String>,
+    /// The synthesized audio of each `Segment::Speech`, in order.
+    #[serde(skip)]
+    pub audio: Vec<Audio>,
+}
+
+impl QueueItem {
+    pub fn speech(&self) -> impl Iterator<Item = &TtsRequest> {
+        self.segments.iter().filter_map(|segment| match segment {
+            Segment::Speech(request) => Some(request),
+            _ => None,
+        })
+    }
+
+    /// The whole text of the item, for display.
+    pub fn text(&self) -> String {
+        self.segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Speech(request) => request.text.clone(),
+                Segment::Beep => "*beep*".to_string(),
+                Segment::Sound(name) => format!("({})", name),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn speakers(&self) -> String {
+        let mut speakers = self
+            .speech()
+            .map(|request| request.speaker.as_str())
+            .collect::<Vec<_>>();
+        speakers.dedup();
+        speakers.join(", ")
+    }
+}
+
+/// The TTS requests, in the order they will be played.
+#[derive(Debug, Default)]
+pub struct Queue {
+    items: Vec<QueueItem>,
+    next_id: u64,
+}
+
+pub fn get_queue_file_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("queue.json");
+    path
+}
+
+impl Queue {
+    /// Load the items which were left unplayed the last time the app was closed.
+    ///
+    /// Audio isn't saved, so every item has to be synthesized again.
+    pub fn load() -> Queue {
+        let items = std::fs::read_to_string(get_queue_file_path())
+            .ok()
+            .and_then(|file| serde_json::from_str::<Vec<QueueItem>>(&file).ok())
+            .unwrap_or_default()
+            .into_iter()
+            .map(|item| QueueItem {
+                status: match item.status {
+                    Status::AwaitingApproval => Status::AwaitingApproval,
+                    _ => Status::Pending,
+                },
+                ..item
+            })
+            .collect::<Vec<_>>();
+        let next_id = items.iter().map(|item| item.id + 1).max().unwrap_or(0);
+        Queue { items, next_id }
+    }
+
+    pub fn save(&self) {
+        let items = self
+            .items
+            .iter()
+            .filter(|item| item.status != Status::Failed)
+            .collect::<Vec<_>>();
+        match serde_json::to_string(&items) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(get_queue_file_path(), json) {
+                    log::error!("Failed to save the queue: {}", e);
+                }
+            }
+            Err(e) => log::error!("Failed to serialize the queue: {}", e),
+        }
+    }
+
+    pub fn items(&self) -> &[QueueItem] {
+        &self.items
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn push(&mut self, requester: &str, segments: Vec<Segment>, status: Status) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.items.push(QueueItem {
+            id,
+            requester: requester.to_string(),
+            segments,
+            status,
+            error: None,
+            audio: Vec::new(),
+        });
+        id
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut QueueItem> {
+        self.items.iter_mut().find(|item| item.id == id)
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<QueueItem> {
+        let index = self.items.iter().position(|item| item.id == id)?;
+        Some(self.items.remove(index))
+    }
+
+    /// Move an item one place towards the front (`up`) or the back of the queue.
+    ///
+    /// The item which is currently playing can't be moved, nor can anything move in front of it.
+    pub fn move_item(&mut self, id: u64, up: bool) {
+        let index = match self.items.iter().position(|item| item.id == id) {
+            Some(index) => index,
+            None => return,
+        };
+        let other = if up {
+            index.checked_sub(1)
+        } else {
+            Some(index + 1).filter(|i| *i < self.items.len())
+        };
+        if let Some(other) = other {
+            if self.items[index].status != Status::Playing
+                && self.items[other].status != Status::Playing
+            {
+                self.items.swap(index, other);
+            }
+        }
+    }
+
+    /// Remove everything except the item which is currently playing.
+    pub fn clear(&mut self) {
+        self.items.retain(|item| item.status == Status::Playing);
+    }
+
+    pub fn playing(&self) -> Option<&QueueItem> {
+        self.items
+            .iter()
+            .find(|item| item.status == Status::Playing)
+    }
+
+    /// The item that should play next. Items are played in order, so this
+    /// returns an item even if it hasn't been synthesized yet.
+    pub fn next(&mut self) -> Option<&mut QueueItem> {
+        self.items.iter_mut().find(|item| {
+            !matches!(
+                item.status,
+                Status::Failed | Status::Playing | Status::AwaitingApproval
+            )
+        })
+    }
+
+    /// IDs of the items which still need to be synthesized.
+    pub fn pending(&self) -> Vec<u64> {
+        self.items
+            .iter()
+            .filter(|item| item.status == Status::Pending)
+            .map(|item| item.id)
+            .collect()
+    }
+}
@@ -1,10 +1,74 @@
-use crate::msg;
+use crate::{browser_output, msg, overlay, queue::Status, tts};
+use futures::{SinkExt, Stream, StreamExt};
+use serde::Serialize;
 use std::collections::HashMap;
+use warp::ws::{Message, WebSocket};
 
-pub async fn start(msg: msg::Sender) {
+/// A queue item, as shown in the web dock.
+#[derive(Serialize)]
+struct DockItem {
+    id: u64,
+    requester: String,
+    speakers: String,
+    text: String,
+    status: Status,
+}
+
+/// Send `messages` to a page until either runs out.
+///
+/// The pages don't send anything, so reading from the socket only notices when they disconnect.
+async fn push(socket: WebSocket, messages: impl Stream<Item = Message>) {
+    let (mut sender, mut receiver) = socket.split();
+    let disconnected = async { while let Some(Ok(_)) = receiver.next().await {} };
+    let forward = async {
+        futures::pin_mut!(messages);
+        while let Some(message) = messages.next().await {
+            if sender.send(message).await.is_err() {
+                return;
+            }
+        }
+    };
+    tokio::select! {
+        _ = disconnected => {}
+        _ = forward => {}
+    }
+}
+
+/// Whether a request comes from a page served by us rather than from another website.
+///
+/// Browsers always send the origin with cross-origin POSTs, so requests without one come from
+/// other programs running on this machine.
+fn is_local_origin(origin: Option<&str>) -> bool {
+    match origin {
+        None => true,
+        Some(origin) => {
+            let host = origin
+                .strip_prefix("http://")
+                .unwrap_or(origin)
+                .rsplitn(2, ':')
+                .last()
+                .unwrap_or_default();
+            host == "localhost" || host == "127.0.0.1"
+        }
+    }
+}
+
+/// Run `action` only if the request comes from a local page.
+fn local_only(origin: Option<String>, action: impl FnOnce()) -> impl warp::Reply {
+    if is_local_origin(origin.as_deref()) {
+        action();
+        warp::http::StatusCode::OK
+    } else {
+        log::warn!("Rejected a request from {:?}", origin);
+        warp::http::StatusCode::FORBIDDEN
+    }
+}
+
+pub async fn start(msg: msg::Sender, tts: tts::TtsCtx) {
     use warp::Filter;
 
     let msg = warp::any().map(move || msg.clone());
+    let tts = warp::any().map(move || tts.clone());
 
     let twitch_token = warp::path("twitch_token")
         .and(warp::query::<HashMap<String, String>>())
@@ -27,7 +91,81 @@ pub async fn start(msg: msg::Sender) {
         },
     );
 
-    warp::serve(twitch_token.or(twitch_response))
-        .run(([127, 0, 0, 1], 3030))
-        .await;
+    let dock = warp::path!("dock").map(|| warp::reply::html(include_str!("./dock.html")));
+
+    let overlay = warp::path!("overlay").map(|| warp::reply::html(overlay::html()));
+
+    let overlay_css = warp::path!("overlay" / "style.css")
+        .map(|| warp::reply::with_header(overlay::css(), "content-type", "text/css"));
+
+    let overlay_ws = warp::path!("overlay" / "ws")
+        .and(warp::ws())
+        .and(tts.clone())
+        .map(|ws: warp::ws::Ws, tts: tts::TtsCtx| {
+            let now_playing = tts.now_playing();
+            ws.on_upgrade(move |socket| push(socket, overlay::updates(now_playing)))
+        });
+
+    let audio = warp::path!("audio").map(|| warp::reply::html(include_str!("./audio.html")));
+
+    let audio_ws = warp::path!("audio" / "ws")
+        .and(warp::ws())
+        .and(tts.clone())
+        .map(|ws: warp::ws::Ws, tts: tts::TtsCtx| {
+            let audio = tts.browser_audio();
+            ws.on_upgrade(move |socket| push(socket, browser_output::messages(audio)))
+        });
+
+    let queue = warp::get()
+        .and(warp::path!("api" / "queue"))
+        .and(tts.clone())
+        .map(|tts: tts::TtsCtx| {
+            let queue = tts.queue.lock().unwrap();
+            let items = queue
+                .items()
+                .iter()
+                .map(|item| DockItem {
+                    id: item.id,
+                    requester: item.requester.clone(),
+                    speakers: item.speakers(),
+                    text: item.text(),
+                    status: item.status,
+                })
+                .collect::<Vec<_>>();
+            warp::reply::json(&items)
+        });
+
+    let approve = warp::post()
+        .and(warp::path!("api" / "queue" / u64 / "approve"))
+        .and(warp::header::optional::<String>("origin"))
+        .and(tts.clone())
+        .map(|id: u64, origin: Option<String>, tts: tts::TtsCtx| {
+            local_only(origin, || {
+                tokio::spawn(tts::approve(&tts, id));
+            })
+        });
+
+    let reject = warp::post()
+        .and(warp::path!("api" / "queue" / u64 / "reject"))
+        .and(warp::header::optional::<String>("origin"))
+        .and(tts)
+        .map(|id: u64, origin: Option<String>, tts: tts::TtsCtx| {
+            local_only(origin, || tts::reject(&tts, id))
+        });
+
+    warp::serve(
+        twitch_token
+            .or(twitch_response)
+            .or(dock)
+            .or(overlay)
+            .or(overlay_css)
+            .or(overlay_ws)
+            .or(audio)
+            .or(audio_ws)
+            .or(queue)
+            .or(approve)
+            .or(reject),
+    )
+    .run(([127, 0, 0, 1], 3030))
+    .await;
 }
@@ -0,0 +1,114 @@
+use crate::{
+    backend::{Audio, AudioFormat},
+    queue::Segment,
+};
+use std::path::PathBuf;
+
+/// More sound effects than this in one message are read as text.
+pub const MAX_SOUNDS_PER_MESSAGE: usize = 5;
+
+pub fn get_sounds_dir_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("sounds");
+    path
+}
+
+/// The sound effects in the sounds folder, named after their file without the extension.
+pub fn list() -> Vec<String> {
+    let entries = match std::fs::read_dir(get_sounds_dir_path()) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut sounds = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.is_file() {
+                Some(path.file_stem()?.to_str()?.to_lowercase())
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    sounds.sort();
+    sounds
+}
+
+/// Read the file of the sound effect `name`.
+pub fn load(name: &str) -> Option<Audio> {
+    let path = std::fs::read_dir(get_sounds_dir_path())
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .map_or(false, |stem| stem.eq_ignore_ascii_case(name))
+        })?;
+    let format = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("wav") => AudioFormat::Wav,
+        Some("mp3") => AudioFormat::Mp3,
+        Some("ogg") => AudioFormat::Ogg,
+        Some("flac") => AudioFormat::Flac,
+        _ => AudioFormat::Unknown,
+    };
+    match std::fs::read(&path) {
+        Ok(bytes) => Some(Audio { bytes, format }),
+        Err(e) => {
+            log::error!("Failed to read {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Split the speech segments around `(name)` tokens, which are replaced by the sound effect `name`.
+///
+/// Parentheses around anything which isn't a sound effect are removed.
+pub fn insert_sounds(segments: Vec<Segment>) -> Vec<Segment> {
+    let sounds = list();
+    let mut count = 0;
+    let mut result = Vec::new();
+    for segment in segments {
+        let request = match segment {
+            Segment::Speech(request) => request,
+            other => {
+                result.push(other);
+                continue;
+            }
+        };
+
+        let mut text = String::new();
+        let mut rest = request.text.as_str();
+        while let Some(start) = rest.find('(') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start + 1..];
+            let end = match rest.find(')') {
+                Some(end) => end,
+                None => break,
+            };
+            let name = rest[..end].trim().to_lowercase();
+            if count < MAX_SOUNDS_PER_MESSAGE && sounds.contains(&name) {
+                count += 1;
+                push_speech(&mut result, &request.speaker, &text);
+                text.clear();
+                result.push(Segment::Sound(name));
+            } else {
+                text.push_str(&rest[..end]);
+            }
+            rest = &rest[end + 1..];
+        }
+        text.push_str(rest);
+        push_speech(&mut result, &request.speaker, &text);
+    }
+    result
+}
+
+fn push_speech(segments: &mut Vec<Segment>, speaker: &str, text: &str) {
+    let text = text.replace(')', "");
+    if !text.trim().is_empty() {
+        segments.push(Segment::Speech(crate::tts::TtsRequest {
+            text: text.trim().to_string(),
+            speaker: speaker.to_string(),
+        }));
+    }
+}
@@ -1,6 +1,13 @@
-use std::collections::HashMap;
+use crate::backend::TtsBackend;
+use rand::seq::IteratorRandom;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
 
 lazy_static::lazy_static! {
+    /// The built-in aliases. Used as is if the voice catalogue can't be fetched.
     pub static ref TTS_SPEAKERS: HashMap<&'static str, &'static str> = {
         let mut speakers = HashMap::new();
         speakers.insert("altman", "sam-altman");
@@ -78,4 +85,185 @@ lazy_static::lazy_static! {
         speakers.insert("zuckerberg", "mark-zuckerberg");
         speakers
     };
+
+    /// Alias -> voice, merged from the voice catalogue, `TTS_SPEAKERS` and the user's aliases.
+    static ref SPEAKERS: RwLock<HashMap<String, String>> = RwLock::new(merge(load_cache()));
+}
+
+/// Picks a random voice.
+pub const RANDOM_ALIAS: &str = "random";
+/// How many characters a speaker can be misspelled by.
+pub const MAX_EDIT_DISTANCE: usize = 2;
+/// Aliases shorter than this have to be spelled correctly.
+pub const MIN_FUZZY_LENGTH: usize = 4;
+
+pub const DEFAULT_ALIASES_FILE: &str = "# One alias per line, e.g.\n# homer = homer-simpson\n";
+
+pub fn get_voice_cache_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("voices.json");
+    path
+}
+
+pub fn get_aliases_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("speakers.txt");
+    path
+}
+
+/// The voice used for `alias`, which can be misspelled by up to `MAX_EDIT_DISTANCE` characters.
+///
+/// `random` picks any voice.
+pub fn resolve(alias: &str) -> Option<String> {
+    let speakers = SPEAKERS.read().unwrap();
+    if let Some(voice) = speakers.get(alias) {
+        return Some(voice.clone());
+    }
+    let alias = alias.to_lowercase();
+    if alias == RANDOM_ALIAS {
+        return speakers.values().choose(&mut rand::thread_rng()).cloned();
+    }
+    if let Some((_, voice)) = speakers.iter().find(|(a, _)| a.to_lowercase() == alias) {
+        return Some(voice.clone());
+    }
+    // Short aliases are too easy to confuse with ordinary words.
+    if alias.chars().count() < MIN_FUZZY_LENGTH {
+        return None;
+    }
+    let mut matches = speakers
+        .iter()
+        .map(|(a, voice)| (edit_distance(&a.to_lowercase(), &alias), voice))
+        .filter(|(distance, _)| *distance <= MAX_EDIT_DISTANCE)
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|(distance, _)| *distance);
+    match matches.as_slice() {
+        [] => None,
+        // Don't guess between two voices which are equally close.
+        [(a, first), (b, second), ..] if a == b && first != second => None,
+        [(_, voice), ..] => Some(voice.to_string()),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == *cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Every alias and its voice, sorted by alias.
+pub fn all() -> Vec<(String, String)> {
+    let mut speakers = SPEAKERS
+        .read()
+        .unwrap()
+        .iter()
+        .map(|(alias, voice)| (alias.clone(), voice.clone()))
+        .collect::<Vec<_>>();
+    speakers.sort();
+    speakers
+}
+
+/// The names of the voices, without duplicates, for the UI.
+pub fn voices() -> Vec<String> {
+    let mut voices = SPEAKERS
+        .read()
+        .unwrap()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    voices.sort_unstable();
+    voices.dedup();
+    voices
+}
+
+/// Fetch the voice catalogue of `backend` and rebuild the aliases.
+///
+/// If the catalogue can't be fetched, the last one that was fetched is used instead.
+pub async fn refresh(backend: Arc<dyn TtsBackend>) {
+    let catalogue = match backend.voices().await {
+        Ok(voices) if !voices.is_empty() => {
+            log::info!("Fetched {} voices from {}", voices.len(), backend.name());
+            save_cache(&voices);
+            Some(voices)
+        }
+        Ok(_) => None,
+        Err(e) => {
+            log::error!("Failed to fetch the voices from {}: {}", backend.name(), e);
+            load_cache()
+        }
+    };
+    *SPEAKERS.write().unwrap() = merge(catalogue);
+}
+
+/// Every voice in `catalogue` can be used by its name. The built-in aliases are kept for
+/// the voices which still exist, and the user's aliases are added on top.
+fn merge(catalogue: Option<Vec<String>>) -> HashMap<String, String> {
+    let mut speakers = HashMap::new();
+    match catalogue {
+        Some(voices) => {
+            for (alias, voice) in TTS_SPEAKERS.iter() {
+                if voices.iter().any(|v| v == voice) {
+                    speakers.insert(alias.to_string(), voice.to_string());
+                }
+            }
+            for voice in voices {
+                speakers.entry(voice.clone()).or_insert(voice);
+            }
+        }
+        None => {
+            for (alias, voice) in TTS_SPEAKERS.iter() {
+                speakers.insert(alias.to_string(), voice.to_string());
+            }
+        }
+    }
+    speakers.extend(load_aliases());
+    speakers
+}
+
+fn load_cache() -> Option<Vec<String>> {
+    let file = std::fs::read_to_string(get_voice_cache_path()).ok()?;
+    serde_json::from_str(&file).ok()
+}
+
+fn save_cache(voices: &[String]) {
+    match serde_json::to_string(voices) {
+        Ok(json) => {
+            if let Err(e) = std::fs::write(get_voice_cache_path(), json) {
+                log::error!("Failed to save the voice cache: {}", e);
+            }
+        }
+        Err(e) => log::error!("Failed to serialize the voice cache: {}", e),
+    }
+}
+
+/// `<alias> = <voice>`, one per line.
+fn load_aliases() -> Vec<(String, String)> {
+    let path = get_aliases_path();
+    if !path.exists() {
+        if let Err(e) = std::fs::write(&path, DEFAULT_ALIASES_FILE) {
+            log::error!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+    std::fs::read_to_string(&path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (alias, voice) = line.split_once('=')?;
+            let (alias, voice) = (alias.trim(), voice.trim());
+            if alias.is_empty() || voice.is_empty() {
+                return None;
+            }
+            Some((alias.to_string(), voice.to_string()))
+        })
+        .collect()
 }
@@ -1,17 +1,46 @@
-use crate::ui;
+use crate::{
+    backend::{Audio, BackendKind, TtsBackend},
+    browser_output::{self, BrowserAudio, OutputMode},
+    chat::{Chatter, SubKind, Subscription},
+    cooldown::{Cooldown, CooldownConfig},
+    emotes::{self, ThirdPartyEmotes},
+    filter::{self, CensorPolicy},
+    limits::{self, ClipBudget},
+    loudness,
+    moderation::{self, Blocklist, ModCommand},
+    msg, normalize,
+    output_device::OutputDevice,
+    overlay::NowPlaying,
+    pronunciation::{self, Dictionary, Pronunciation},
+    pubsub,
+    queue::{Queue, Segment, Status},
+    sounds, speakers, ui,
+    word_lists::{self, WordLists},
+};
+use anyhow::anyhow;
+use rodio::Source;
 use std::{
+    future::Future,
     io::{BufReader, Cursor},
     num::NonZeroU32,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
     thread::JoinHandle,
+    time::{Duration, Instant},
 };
-use tokio::sync::watch;
+use tokio::sync::{broadcast, mpsc, watch};
 use twitch::Message;
 
 pub const TTS_REQUESTS_PER_MINUTE: u32 = 5;
 pub const RETRY_ATTEMPTS: u8 = 3;
 pub const API_TIMEOUT_SECONDS: u64 = 180;
-pub const API_URL: &str = "https://mumble.stream/speak";
+/// How often the player checks whether the next item can be played.
+pub const PLAYER_POLL_MILLISECONDS: u64 = 100;
+pub const BEEP_FREQUENCY: u32 = 1000;
+pub const BEEP_MILLISECONDS: u64 = 400;
+pub const BEEP_VOLUME: f32 = 0.2;
 
 pub type TtsCtx = Arc<TtsContext>;
 
@@ -23,64 +52,660 @@ pub struct TtsContext {
         governor::clock::DefaultClock,
     >,
     pub banned_words: tokio::sync::Mutex<censor::Censor>,
-    pub queue: rodio::Sink,
+    pronunciations: RwLock<Dictionary>,
+    pub sink: rodio::Sink,
+    output_device: OutputDevice,
+    pub queue: Mutex<Queue>,
+    /// Stops the item which is currently playing.
+    skip_current: Mutex<Option<Arc<AtomicBool>>>,
+    browser_audio: broadcast::Sender<BrowserAudio>,
+    /// When the item sent to the browser source ends.
+    browser_playing_until: Mutex<Option<Instant>>,
+    backend: RwLock<(BackendKind, Arc<dyn TtsBackend>)>,
     state_tx: watch::Sender<ui::State>,
     state_rx: watch::Receiver<ui::State>,
+    now_playing_tx: watch::Sender<Option<NowPlaying>>,
+    now_playing_rx: watch::Receiver<Option<NowPlaying>>,
     client: reqwest::Client,
+    msg: msg::Sender,
 }
 
 impl TtsContext {
-    pub fn new(queue: rodio::Sink) -> Self {
+    pub fn new(sink: rodio::Sink, output_device: OutputDevice, msg: msg::Sender) -> Self {
         let (state_tx, state_rx) = tokio::sync::watch::channel(ui::State::default());
+        let (now_playing_tx, now_playing_rx) = tokio::sync::watch::channel(None);
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(API_TIMEOUT_SECONDS))
+            .build()
+            .unwrap();
+        let backend = BackendKind::default();
         Self {
             tts_limit: governor::RateLimiter::direct(governor::Quota::per_minute(
                 NonZeroU32::new(TTS_REQUESTS_PER_MINUTE).unwrap(),
             )),
-            banned_words: tokio::sync::Mutex::new(
-                censor::Standard - "ass" - "cock" - "pussy" - "fuck" - "piss" - "shit",
-            ),
-            queue,
+            banned_words: tokio::sync::Mutex::new(WordLists::load().build_censor(true)),
+            pronunciations: RwLock::new(Dictionary::new(&pronunciation::load())),
+            sink,
+            output_device,
+            queue: Mutex::new(Queue::load()),
+            skip_current: Mutex::new(None),
+            browser_audio: browser_output::channel(),
+            browser_playing_until: Mutex::new(None),
+            backend: RwLock::new((backend.clone(), backend.create(client.clone()))),
             state_tx,
             state_rx,
-            client: reqwest::Client::builder()
-                .timeout(std::time::Duration::from_secs(API_TIMEOUT_SECONDS))
-                .build()
-                .unwrap(),
+            now_playing_tx,
+            now_playing_rx,
+            client,
+            msg,
         }
     }
 
+    /// The backend currently selected in the UI.
+    pub fn backend(&self) -> Arc<dyn TtsBackend> {
+        self.backend.read().unwrap().1.clone()
+    }
+
     pub fn update_tts_config(&self, state: ui::State) {
+        {
+            let mut backend = self.backend.write().unwrap();
+            if backend.0 != state.backend {
+                log::info!("Switching the TTS backend to {}", state.backend.name());
+                *backend = (
+                    state.backend.clone(),
+                    state.backend.create(self.client.clone()),
+                );
+            }
+        }
+        self.output_device.select(state.output_device.clone());
         let _ = self.state_tx.send(state);
     }
+
+    /// Follows the item which is currently playing, for the overlay.
+    pub fn now_playing(&self) -> watch::Receiver<Option<NowPlaying>> {
+        self.now_playing_rx.clone()
+    }
+
+    /// Save the pronunciation dictionary and use it for the next synthesized requests.
+    pub fn set_pronunciations(&self, entries: &[Pronunciation]) {
+        pronunciation::save(entries);
+        *self.pronunciations.write().unwrap() = Dictionary::new(entries);
+    }
+
+    /// `text` as it is sent to the backend.
+    pub fn respell(&self, text: &str) -> String {
+        self.pronunciations.read().unwrap().apply(text)
+    }
+
+    /// Receives the items played in `OutputMode::Browser`.
+    pub fn browser_audio(&self) -> broadcast::Receiver<BrowserAudio> {
+        self.browser_audio.subscribe()
+    }
+
+    /// Let the streamer know why a request didn't play.
+    pub fn report_dropped(&self, user: &str, reason: impl Into<String>) {
+        let reason = reason.into();
+        log::info!("Dropped a TTS request from {}: {}", user, reason);
+        let _ = self.msg.send(msg::Message::Dropped {
+            user: user.to_string(),
+            reason,
+        });
+    }
+
+    /// Let the streamer know that a request contained banned words.
+    pub fn report_censored(&self, user: &str, policy: CensorPolicy, words: usize) {
+        let decision = format!("{} banned word(s): {}", words, policy.name().to_lowercase());
+        log::info!("Censored a TTS request from {}: {}", user, decision);
+        let _ = self.msg.send(msg::Message::Censored {
+            user: user.to_string(),
+            decision,
+        });
+    }
+
+    /// Run a moderator's chat command and record it in the audit log.
+    fn run_mod_command(
+        &self,
+        state: &mut ui::State,
+        blocklist: &mut Blocklist,
+        moderator: &str,
+        command: ModCommand,
+    ) {
+        match &command {
+            ModCommand::Skip => self.skip(),
+            ModCommand::Pause => self.sink.pause(),
+            ModCommand::Resume => self.sink.play(),
+            ModCommand::Clear => self.clear(),
+            ModCommand::Disable | ModCommand::Enable => {
+                state.enable_tts = command == ModCommand::Enable;
+                let _ = self.msg.send(msg::Message::TtsEnabled(state.enable_tts));
+            }
+            ModCommand::Ban { user, minutes } => {
+                let result = minutes
+                    .checked_mul(60)
+                    .ok_or_else(|| anyhow!("the ban duration is too long"))
+                    .and_then(|seconds| blocklist.block(user, Duration::from_secs(seconds)));
+                if let Err(e) = result {
+                    self.report_mod_error(moderator, e);
+                    return;
+                }
+            }
+            ModCommand::Unban { user } => blocklist.unblock(user),
+        }
+
+        log::info!("{} {}", moderator, command);
+        moderation::audit(moderator, &command);
+        let _ = self.msg.send(msg::Message::Moderation {
+            moderator: moderator.to_string(),
+            action: command.to_string(),
+        });
+    }
+
+    /// Let the streamer know that a moderator's command couldn't be run.
+    fn report_mod_error(&self, moderator: &str, error: anyhow::Error) {
+        log::warn!("Ignored a command from {}: {}", moderator, error);
+        let _ = self.msg.send(msg::Message::Error {
+            what: format!("Ignored a command from {}: {}", moderator, error),
+        });
+    }
+
+    /// Stop the item which is currently playing and move on to the next one.
+    pub fn skip(&self) {
+        if let Some(skip) = &*self.skip_current.lock().unwrap() {
+            skip.store(true, Ordering::SeqCst);
+        }
+        if self.browser_playing_until.lock().unwrap().take().is_some() {
+            let _ = self.browser_audio.send(BrowserAudio::Stop);
+        }
+    }
+
+    /// Remove an item from the queue, skipping it if it is playing.
+    pub fn remove(&self, id: u64) {
+        let mut queue = self.queue.lock().unwrap();
+        if queue.playing().map(|item| item.id) == Some(id) {
+            self.skip();
+        } else {
+            queue.remove(id);
+            queue.save();
+        }
+    }
+
+    pub fn move_item(&self, id: u64, up: bool) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.move_item(id, up);
+        queue.save();
+    }
+
+    /// Remove every item and skip the one which is currently playing.
+    pub fn clear(&self) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.clear();
+        queue.save();
+        self.skip();
+    }
+
+    /// Whether the browser source is still playing an item.
+    fn browser_playing(&self) -> bool {
+        let mut until = self.browser_playing_until.lock().unwrap();
+        match *until {
+            Some(end) if end > Instant::now() => true,
+            _ => {
+                *until = None;
+                false
+            }
+        }
+    }
+
+    /// Start the next item once the current one has finished playing.
+    async fn advance_queue(&self) {
+        if !self.sink.empty() || self.browser_playing() {
+            return;
+        }
+
+        let output = self.state_rx.borrow().output;
+        // The queue is only locked while the item is taken out of it, so that the UI and the chat
+        // aren't held up while the audio is prepared.
+        let (speech, segments, skip) = {
+            let mut queue = self.queue.lock().unwrap();
+            if let Some(id) = queue.playing().map(|item| item.id) {
+                queue.remove(id);
+                queue.save();
+                *self.skip_current.lock().unwrap() = None;
+                let _ = self.now_playing_tx.send(None);
+            }
+
+            // The browser source can't be paused mid-clip, so pausing only holds back the next item.
+            if output == OutputMode::Browser && self.sink.is_paused() {
+                return;
+            }
+
+            let item = match queue.next() {
+                Some(item) if item.status == Status::Ready => item,
+                // Keep the order, even if a later item is already synthesized.
+                _ => return,
+            };
+            let speech = match item.audio.iter().map(decode).collect::<Result<Vec<_>, _>>() {
+                Ok(sources) => sources.into_iter(),
+                Err(e) => {
+                    item.status = Status::Failed;
+                    item.error = Some(e.to_string());
+                    return;
+                }
+            };
+            log::info!("Playing `{}` requested by {}", item.text(), item.requester);
+            item.status = Status::Playing;
+            let _ = self.now_playing_tx.send(Some(NowPlaying {
+                user: item.requester.clone(),
+                speaker: item.speakers(),
+                text: item.text(),
+            }));
+            let skip = Arc::new(AtomicBool::new(false));
+            *self.skip_current.lock().unwrap() = Some(skip.clone());
+            (speech, item.segments.clone(), skip)
+        };
+
+        // Loudness normalization reads the whole clip, so it's kept off the player.
+        let state = self.state_rx.borrow().clone();
+        let sources =
+            match tokio::task::spawn_blocking(move || build_sources(speech, &segments, &state))
+                .await
+            {
+                Ok(sources) => sources,
+                Err(e) => {
+                    log::error!("Failed to prepare the audio: {}", e);
+                    return;
+                }
+            };
+
+        match output {
+            OutputMode::Device => {
+                // Every segment is appended at once, so nothing can play in between them.
+                for source in sources {
+                    self.append_stoppable(source, skip.clone());
+                }
+            }
+            OutputMode::Browser => {
+                let render = tokio::task::spawn_blocking(move || browser_output::render(sources));
+                let (wav, duration) = match render.await {
+                    Ok(rendered) => rendered,
+                    Err(e) => {
+                        log::error!("Failed to render the audio for the browser source: {}", e);
+                        return;
+                    }
+                };
+                // The item may have been skipped while it was rendered.
+                if skip.load(Ordering::SeqCst) {
+                    return;
+                }
+                *self.browser_playing_until.lock().unwrap() = Some(Instant::now() + duration);
+                let _ = self.browser_audio.send(BrowserAudio::Play(Arc::new(wav)));
+            }
+        }
+    }
+
+    fn append_stoppable<S>(&self, source: S, skip: Arc<AtomicBool>)
+    where
+        S: Source + Send + 'static,
+        S::Item: rodio::Sample + Send,
+    {
+        self.sink.append(source.stoppable().periodic_access(
+            Duration::from_millis(5),
+            move |source| {
+                if skip.load(Ordering::SeqCst) {
+                    source.stop();
+                }
+            },
+        ));
+    }
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TtsRequest {
     /// The text to say.
     pub text: String,
     /// The name of the speaker to use.
-    pub speaker: &'static str,
+    pub speaker: String,
+}
+
+/// Turn the segments of an item into sources, applying the audio settings in `state`.
+fn build_sources(
+    mut speech: impl Iterator<Item = rodio::Decoder<BufReader<Cursor<Vec<u8>>>>>,
+    segments: &[Segment],
+    state: &ui::State,
+) -> Vec<Box<dyn Source<Item = f32> + Send>> {
+    let sources = segments
+        .iter()
+        .filter_map(|segment| -> Option<Box<dyn Source<Item = f32> + Send>> {
+            match segment {
+                Segment::Speech(request) => {
+                    let source = speech.next()?.convert_samples();
+                    let gain = state.speaker_gain(&request.speaker);
+                    if state.normalize_loudness {
+                        Some(Box::new(loudness::normalize(source).amplify(gain)))
+                    } else {
+                        Some(Box::new(source.amplify(gain)))
+                    }
+                }
+                Segment::Beep => Some(Box::new(
+                    rodio::source::SineWave::new(BEEP_FREQUENCY)
+                        .take_duration(Duration::from_millis(BEEP_MILLISECONDS))
+                        .amplify(BEEP_VOLUME),
+                )),
+                Segment::Sound(name) => match sounds::load(name).map(|audio| decode(&audio)) {
+                    Some(Ok(source)) => Some(Box::new(source.convert_samples())),
+                    Some(Err(e)) => {
+                        log::error!("Failed to decode the sound effect {}: {}", name, e);
+                        None
+                    }
+                    None => {
+                        log::error!("The sound effect {} doesn't exist", name);
+                        None
+                    }
+                },
+            }
+        })
+        // NOTE: changing the speed also changes the pitch.
+        .map(|source| -> Box<dyn Source<Item = f32> + Send> {
+            Box::new(source.amplify(state.volume).speed(state.speed))
+        })
+        .collect::<Vec<_>>();
+    if state.max_clip_seconds > 0 {
+        // The segments share the budget, so the whole item is cut off.
+        let budget = ClipBudget::new(Duration::from_secs(state.max_clip_seconds));
+        sources
+            .into_iter()
+            .map(|source| -> Box<dyn Source<Item = f32> + Send> { Box::new(budget.limit(source)) })
+            .collect()
+    } else {
+        sources
+    }
+}
+
+fn decode(
+    audio: &Audio,
+) -> Result<rodio::Decoder<BufReader<Cursor<Vec<u8>>>>, rodio::decoder::DecoderError> {
+    rodio::Decoder::new(BufReader::new(Cursor::new(audio.bytes.clone())))
 }
 
-pub async fn make_tts_request(ctx: TtsCtx, request: TtsRequest) {
-    ctx.tts_limit.until_ready().await;
-    log::info!("Received a filtered tts request: {:#?}", request);
+/// Whether a request from `chatter` has to be approved by the streamer before it is played.
+fn needs_approval(state: &ui::State, chatter: &Chatter) -> bool {
+    let auto_approved = (state.auto_approve_mods && chatter.is_moderator())
+        || (state.auto_approve_vips && chatter.has_badge("vip"))
+        || (state.auto_approve_subs
+            && (chatter.has_badge("subscriber") || chatter.has_badge("founder")));
+    state.hold_for_approval && !auto_approved
+}
 
+/// "<user> says", read by the announcer before the message.
+///
+/// Without an announcer, the name is read by `first_speaker`, the voice of the message.
+fn announcement(
+    state: &ui::State,
+    chatter: &Chatter,
+    first_speaker: Option<&str>,
+) -> Option<TtsRequest> {
+    if !state.announce_user {
+        return None;
+    }
+    let speaker = match state.announcer_speaker.trim() {
+        "" => first_speaker
+            .map(|speaker| speaker.to_string())
+            .or_else(|| speakers::resolve(state.default_speaker.trim())),
+        announcer => speakers::resolve(announcer),
+    };
+    let speaker = match speaker {
+        Some(speaker) => speaker,
+        None => {
+            log::warn!(
+                "Not announcing {}: the announcer `{}` is unknown",
+                chatter.display_name,
+                state.announcer_speaker
+            );
+            return None;
+        }
+    };
+    let name = state
+        .name_pronunciations
+        .get(&chatter.display_name.to_lowercase())
+        .unwrap_or(&chatter.display_name)
+        .as_str();
+    let text = normalize::normalize(&crate::template::render(
+        &state.announce_template,
+        &[("user", name)],
+    ));
+    if text.is_empty() {
+        return None;
+    }
+    Some(TtsRequest { text, speaker })
+}
+
+/// Run the requests through the censor, then add them to the queue as one item.
+///
+/// If `announce` is set, the item starts with the name of the chatter, see `announcement`.
+pub async fn submit(ctx: &TtsCtx, chatter: &Chatter, requests: Vec<TtsRequest>, announce: bool) {
+    let requester = &chatter.display_name;
+    let (policy, substitute, hold, requests, intro) = {
+        let state = ctx.state_rx.borrow();
+        let requests = limits::limit_length(
+            requests,
+            state.max_message_length,
+            state.long_message_policy,
+        );
+        let intro = match &requests {
+            Some(requests) if announce => announcement(
+                &state,
+                chatter,
+                requests.first().map(|request| request.speaker.as_str()),
+            ),
+            _ => None,
+        };
+        (
+            state.censor_policy,
+            state.censor_substitute.clone(),
+            needs_approval(&state, chatter),
+            requests,
+            intro,
+        )
+    };
+    let requests = match requests {
+        Some(requests) => requests,
+        None => {
+            ctx.report_dropped(requester, "the message is too long");
+            return;
+        }
+    };
+    let (banned, intro) = {
+        let censor = ctx.banned_words.lock().await;
+        (
+            requests
+                .iter()
+                .map(|request| filter::find_banned_words(&censor, &request.text))
+                .collect::<Vec<_>>(),
+            // Leave out names with banned words, the message itself can still be read.
+            intro.filter(|intro| filter::find_banned_words(&censor, &intro.text).is_empty()),
+        )
+    };
+    let banned_count = banned.iter().map(Vec::len).sum::<usize>();
+    let status = if hold {
+        Status::AwaitingApproval
+    } else {
+        Status::Pending
+    };
+
+    let (segments, status) = if banned_count == 0 {
+        (requests.into_iter().map(Segment::Speech).collect(), status)
+    } else {
+        let censored = censor_segments(policy, requests, &banned, &substitute, status);
+        match censored {
+            Some(censored) => {
+                ctx.report_censored(requester, policy, banned_count);
+                censored
+            }
+            None => {
+                ctx.report_dropped(requester, "the message contains banned words");
+                return;
+            }
+        }
+    };
+    // The announcement is part of the same item, so nothing can play in between.
+    let segments = intro
+        .map(Segment::Speech)
+        .into_iter()
+        .chain(segments)
+        .collect();
+    make_tts_request(ctx, requester, segments, status);
+}
+
+/// Apply `policy` to requests which contain banned words, `None` means the requests are dropped.
+fn censor_segments(
+    policy: CensorPolicy,
+    requests: Vec<TtsRequest>,
+    banned: &[Vec<usize>],
+    substitute: &str,
+    status: Status,
+) -> Option<(Vec<Segment>, Status)> {
+    let censored = match policy {
+        CensorPolicy::Drop => return None,
+        CensorPolicy::Substitute => (
+            requests
+                .iter()
+                .zip(banned)
+                .map(|(request, banned)| {
+                    Segment::Speech(filter::substitute(request, banned, substitute))
+                })
+                .collect(),
+            status,
+        ),
+        CensorPolicy::Beep => (
+            requests
+                .iter()
+                .zip(banned)
+                .flat_map(|(request, banned)| filter::beep(request, banned))
+                .collect(),
+            status,
+        ),
+        CensorPolicy::Approve => (
+            requests.into_iter().map(Segment::Speech).collect(),
+            Status::AwaitingApproval,
+        ),
+    };
+    Some(censored)
+}
+
+/// Add the segments to the queue and start synthesizing them, unless they need to be approved first.
+///
+/// Must be called from within the runtime.
+pub fn make_tts_request(
+    ctx: &TtsCtx,
+    requester: &str,
+    segments: Vec<Segment>,
+    status: Status,
+) -> u64 {
+    let segments = sounds::insert_sounds(segments);
+    let id = {
+        let mut queue = ctx.queue.lock().unwrap();
+        let id = queue.push(requester, segments, status);
+        queue.save();
+        id
+    };
+    if status == Status::Pending {
+        tokio::spawn(synthesize_item(ctx.clone(), id));
+    }
+    id
+}
+
+/// Let a held request through. The returned future synthesizes it.
+pub fn approve(ctx: &TtsCtx, id: u64) -> impl Future<Output = ()> {
+    {
+        let mut queue = ctx.queue.lock().unwrap();
+        if let Some(item) = queue.get_mut(id) {
+            if item.status == Status::AwaitingApproval {
+                item.status = Status::Pending;
+            }
+        }
+        queue.save();
+    }
+    synthesize_item(ctx.clone(), id)
+}
+
+pub fn reject(ctx: &TtsCtx, id: u64) {
+    let mut queue = ctx.queue.lock().unwrap();
+    if let Some(item) = queue.remove(id) {
+        queue.save();
+        drop(queue);
+        ctx.report_dropped(&item.requester, "the request was rejected");
+    }
+}
+
+async fn synthesize_item(ctx: TtsCtx, id: u64) {
+    let requests = {
+        let mut queue = ctx.queue.lock().unwrap();
+        match queue.get_mut(id) {
+            // Claim the item, so it is never synthesized twice.
+            Some(item) if item.status == Status::Pending => {
+                item.status = Status::Synthesizing;
+                item.speech().cloned().collect::<Vec<_>>()
+            }
+            _ => return,
+        }
+    };
+
+    // The segments are synthesized concurrently, but each of them still has to go through the rate limiter.
+    let results = futures::future::join_all(requests.iter().map(|request| {
+        let ctx = ctx.clone();
+        async move {
+            ctx.tts_limit.until_ready().await;
+            let removed = ctx.queue.lock().unwrap().get_mut(id).is_none();
+            if removed {
+                // The item was removed while it was waiting for the rate limiter.
+                return Err("The request was removed".to_string());
+            }
+            log::info!("Received a filtered tts request: {:#?}", request);
+            synthesize(&ctx, request).await
+        }
+    }))
+    .await;
+    let result = results.into_iter().collect::<Result<Vec<_>, _>>();
+
+    let failed = {
+        let mut queue = ctx.queue.lock().unwrap();
+        match (queue.get_mut(id), result) {
+            (Some(item), Ok(audio)) => {
+                item.status = Status::Ready;
+                item.audio = audio;
+                None
+            }
+            (Some(item), Err(error)) => {
+                item.status = Status::Failed;
+                item.error = Some(error);
+                Some(item.requester.clone())
+            }
+            (None, _) => None,
+        }
+    };
+    if let Some(requester) = failed {
+        ctx.report_dropped(&requester, "the synthesis failed");
+    }
+}
+
+async fn synthesize(ctx: &TtsCtx, request: &TtsRequest) -> Result<Audio, String> {
+    let backend = ctx.backend();
+    let text = ctx.respell(&request.text);
     let mut last_error = None;
 
     for i in 0..RETRY_ATTEMPTS {
         log::debug!(
-            "[{} / {}] Performing the request ...",
+            "[{} / {}] Performing the request using {} ...",
             i + 1,
-            RETRY_ATTEMPTS
+            RETRY_ATTEMPTS,
+            backend.name()
         );
-        let response = match ctx.client.post(API_URL).json(&request).send().await {
-            Ok(resp) => resp,
+        // Retry on connection and server errors.
+        let audio = match backend.synthesize(&text, &request.speaker).await {
+            Ok(audio) => audio,
             Err(e) => {
-                // Retry on connection errors.
-                log::debug!(
-                    "[{} / {}] Failed to receive a response: {}",
-                    i,
+                log::info!(
+                    "[{} / {}] Failed to synthesize the audio: {}, retrying",
+                    i + 1,
                     RETRY_ATTEMPTS,
                     e
                 );
@@ -89,82 +714,205 @@ pub async fn make_tts_request(ctx: TtsCtx, request: TtsRequest) {
             }
         };
         log::info!(
-            "[{} / {}] Received a response from the server; STATUS = {}",
+            "[{} / {}] Received {} bytes of {:?} audio from the backend",
             i + 1,
             RETRY_ATTEMPTS,
-            response.status()
+            audio.bytes.len(),
+            audio.format
         );
-        log::debug!("{:#?}", response);
-
-        // Retry on server errors.
-        let status = response.status();
-        if !status.is_success() {
-            log::info!(
-                "[{} / {}] Response wasn't a success, retrying",
-                i + 1,
-                RETRY_ATTEMPTS,
-            );
-            last_error = Some(format!(
-                "HTTP {} - {}",
-                status,
-                response
-                    .text()
-                    .await
-                    .unwrap_or_else(|_| "<no response body>".to_string())
-            ));
-            continue;
-        }
-        let bytes = response.bytes().await;
-        if bytes.is_err() {
-            log::info!(
-                "[{} / {}] Couldn't read the response bytes, retrying",
-                i + 1,
-                RETRY_ATTEMPTS,
-            );
-            continue;
-        }
 
-        match rodio::Decoder::new(BufReader::new(Cursor::new(bytes.unwrap().to_vec()))) {
-            Ok(audio) => {
+        match decode(&audio) {
+            Ok(_) => {
                 log::info!("Successfully decoded the audio, queueing...");
-                ctx.queue.append(audio);
-                return;
+                return Ok(audio);
             }
             Err(e) => {
                 log::error!("Failed to decode the audio: {}. Retrying the request...", e);
+                last_error = Some(e.to_string());
             }
         }
     }
 
-    // QQQQ: Requeue the request here or let the streamer know, if it was purchased for points?
     log::info!("All attempts to fullfil the request have been exhausted; ignoring the request");
     log::debug!("Last error was:\n{:#?}", last_error);
+    Err(last_error.unwrap_or_else(|| "Unknown error".to_string()))
+}
+
+/// Rebuild the censor whenever the word lists or the standard list toggle change.
+async fn watch_word_lists(ctx: TtsCtx) {
+    let mut state_rx = ctx.state_rx.clone();
+    let mut last = None;
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(word_lists::RELOAD_INTERVAL_SECONDS));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            Ok(_) = state_rx.changed() => {}
+        }
+
+        let include_standard = state_rx.borrow().include_standard_words;
+        let current = (WordLists::modified(), include_standard);
+        if last.as_ref() != Some(&current) {
+            let lists = WordLists::load();
+            log::info!(
+                "Loaded {} banned and {} allowed words (standard list {})",
+                lists.banned.len(),
+                lists.allowed.len(),
+                if include_standard {
+                    "included"
+                } else {
+                    "excluded"
+                }
+            );
+            *ctx.banned_words.lock().await = lists.build_censor(include_standard);
+            last = Some(current);
+        }
+    }
+}
+
+/// Plays the queue, in order.
+async fn run_player(ctx: TtsCtx) {
+    // Synthesize the items which were saved the last time the app was closed.
+    let pending = ctx.queue.lock().unwrap().pending();
+    for id in pending {
+        tokio::spawn(synthesize_item(ctx.clone(), id));
+    }
+
+    let mut interval = tokio::time::interval(Duration::from_millis(PLAYER_POLL_MILLISECONDS));
+    loop {
+        interval.tick().await;
+        ctx.advance_queue().await;
+    }
 }
 
 /// TTS command syntax:
 /// ```
 /// !tts <speaker>: <text>
+/// !tts <speaker>: <text> | <speaker>: <text> | ...
 /// ```
-pub fn parse_tts_request(message: &str) -> Option<TtsRequest> {
-    message
-        .trim()
-        .split_once(":")
-        .map(|(l, r)| (l.trim(), r.trim()))
-        .and_then(|(speaker, text)| {
-            let text = text
-                .replace(|c: char| c.is_ascii_whitespace(), " ")
-                .chars()
-                .filter(|c| {
-                    c.is_ascii_digit()
-                        || c.is_ascii_alphabetic()
-                        || c.is_ascii_whitespace()
-                        || [',', '.', '!', '?', '$', '\''].contains(c)
-                })
-                .collect::<String>();
-
-            let speaker = crate::speakers::TTS_SPEAKERS.get(speaker)?;
-            Some(TtsRequest { text, speaker })
+/// A `|` which isn't followed by a speaker is part of the text. Without a speaker
+/// at the start, the whole first part is read by `default_speaker`, if it is set.
+pub fn parse_tts_request(message: &str, default_speaker: &str) -> Option<Vec<TtsRequest>> {
+    let mut parts = message.split('|');
+    let first = parts.next()?;
+    let (speaker, text) = parse_segment(first).or_else(|| {
+        let default_speaker = default_speaker.trim();
+        if default_speaker.is_empty() {
+            return None;
+        }
+        Some((speakers::resolve(default_speaker)?, first.trim()))
+    })?;
+    let mut segments = vec![(speaker, text.to_string())];
+    for part in parts {
+        match parse_segment(part) {
+            Some((speaker, text)) => segments.push((speaker, text.to_string())),
+            None => {
+                let (_, text) = segments.last_mut().unwrap();
+                text.push('|');
+                text.push_str(part);
+            }
+        }
+    }
+    let requests = segments
+        .into_iter()
+        .map(|(speaker, text)| TtsRequest {
+            text: normalize::normalize(&text),
+            speaker,
         })
+        .filter(|request| !request.text.is_empty())
+        .collect::<Vec<_>>();
+    // Don't send requests which have nothing left to say.
+    if requests.is_empty() {
+        return None;
+    }
+    Some(requests)
+}
+
+/// `<speaker>: <text>`
+fn parse_segment(segment: &str) -> Option<(String, &str)> {
+    let (speaker, text) = segment.trim().split_once(":")?;
+    let speaker = speakers::resolve(speaker.trim())?;
+    Some((speaker, text.trim()))
+}
+
+/// Cheers are read even without the TTS command or a speaker prefix, in which case
+/// the configured cheer speaker (or the default speaker) is used.
+///
+/// `message` has to be stripped of cheermotes already.
+fn parse_cheer_request(state: &ui::State, message: &str) -> Option<Vec<TtsRequest>> {
+    let command = format!("!{} ", state.command_name);
+    let text = message.strip_prefix(&command).unwrap_or(message);
+
+    let default_speaker = if state.cheer_speaker.trim().is_empty() {
+        &state.default_speaker
+    } else {
+        &state.cheer_speaker
+    };
+    parse_tts_request(text, default_speaker)
+}
+
+/// Build the text read for a subscription from the configured templates.
+fn parse_sub_request(
+    state: &ui::State,
+    subscription: &Subscription,
+    message: &str,
+) -> Option<TtsRequest> {
+    let template = match subscription.kind {
+        SubKind::Sub => &state.sub_template,
+        SubKind::Resub => &state.resub_template,
+        SubKind::Gift => &state.gift_template,
+        SubKind::MysteryGift => &state.mystery_gift_template,
+    };
+    let months = subscription.months.to_string();
+    let count = subscription.count.to_string();
+    let text = crate::template::render(
+        template,
+        &[
+            ("user", &subscription.user),
+            ("months", &months),
+            ("recipient", &subscription.recipient),
+            ("count", &count),
+            ("message", message),
+        ],
+    );
+
+    let text = normalize::normalize(&text);
+    if text.is_empty() {
+        return None;
+    }
+    let speaker = if state.sub_speaker.trim().is_empty() {
+        &state.default_speaker
+    } else {
+        &state.sub_speaker
+    };
+    Some(TtsRequest {
+        text,
+        speaker: speakers::resolve(speaker.trim())?,
+    })
+}
+
+/// Whether the redemption listener should be running for `state`.
+fn wants_pubsub(state: &ui::State) -> bool {
+    state.token.is_some() && !state.redemption_reward.trim().is_empty()
+}
+
+async fn handle_redemption(ctx: &TtsCtx, redemption: pubsub::Redemption) {
+    // NOTE: the points can't be refunded, but the streamer can see it in the log.
+    let default_speaker = ctx.state_rx.borrow().default_speaker.clone();
+    match parse_tts_request(&redemption.user_input, &default_speaker) {
+        Some(requests) => {
+            // PubSub doesn't tell us the badges of the user.
+            let chatter = Chatter {
+                display_name: redemption.user_name.clone(),
+                ..Chatter::default()
+            };
+            submit(ctx, &chatter, requests, true).await
+        }
+        None => ctx.report_dropped(
+            &redemption.user_name,
+            "the redemption text isn't in the `<speaker>: <text>` format",
+        ),
+    }
 }
 
 pub fn start_tts_thread(
@@ -172,20 +920,33 @@ pub fn start_tts_thread(
     rt: Arc<tokio::runtime::Runtime>,
     mut stop_recv: tokio::sync::mpsc::Receiver<()>,
 ) -> JoinHandle<()> {
-    // TODO: Do pub/sub here
-
     std::thread::spawn({
         move || {
             log::info!("Started the TTS thread.");
             rt.block_on( async {
+                tokio::spawn(run_player(ctx.clone()));
+                tokio::spawn(watch_word_lists(ctx.clone()));
+                tokio::spawn(speakers::refresh(ctx.backend()));
+
                 let mut conn = twitch::connect(twitch::Config::default()).await.unwrap();
                 let mut state = ui::State::default();
                 let mut state_rx = ctx.state_rx.clone();
+                let mut cooldown = Cooldown::default();
+                let mut blocklist = Blocklist::default();
+                let mut third_party_emotes = ThirdPartyEmotes::default();
+                let mut sound_names = Vec::new();
+                let mut reload = tokio::time::interval(Duration::from_secs(word_lists::RELOAD_INTERVAL_SECONDS));
+                let (redemption_tx, mut redemption_rx) = mpsc::channel(16);
+                let mut pubsub_task: Option<tokio::task::JoinHandle<()>> = None;
 
                 loop {
 
                     tokio::select! {
                         _ = stop_recv.recv() => break,
+                        _ = reload.tick() => {
+                            third_party_emotes.reload();
+                            sound_names = sounds::list();
+                        },
                         Ok(_) = state_rx.changed() => {
                             let new_state = state_rx.borrow().clone();
 
@@ -204,22 +965,139 @@ pub fn start_tts_thread(
                                 log::info!("Joined channel: `{}`", new_state.channel);
                             }
 
+                            if new_state.command_cooldown != state.command_cooldown {
+                                match CooldownConfig::parse(&new_state.command_cooldown) {
+                                    Some(config) => {
+                                        log::info!("Command cooldown set to {:?}", config);
+                                        cooldown.configure(config);
+                                    }
+                                    None => {
+                                        let what = format!("Invalid command cooldown `{}`", new_state.command_cooldown);
+                                        log::warn!("{}", what);
+                                        let _ = ctx.msg.send(msg::Message::Error { what });
+                                    }
+                                }
+                            }
+
+                            if new_state.backend != state.backend {
+                                tokio::spawn(speakers::refresh(ctx.backend()));
+                            }
+
+                            if new_state.token != state.token
+                                || new_state.pubsub_url != state.pubsub_url
+                                || new_state.validate_url != state.validate_url
+                                || wants_pubsub(&new_state) != wants_pubsub(&state)
+                            {
+                                if let Some(task) = pubsub_task.take() {
+                                    task.abort();
+                                }
+                                if wants_pubsub(&new_state) {
+                                    log::info!("Listening to channel point redemptions");
+                                    pubsub_task = Some(tokio::spawn(pubsub::run(
+                                        ctx.client.clone(),
+                                        new_state.pubsub_url.clone(),
+                                        new_state.validate_url.clone(),
+                                        new_state.token.clone().unwrap_or_default(),
+                                        redemption_tx.clone(),
+                                    )));
+                                }
+                            }
+
                             state = new_state;
                         },
+                        Some(redemption) = redemption_rx.recv() => {
+                            blocklist.saw(&redemption.user_login, &redemption.user_id);
+                            if blocklist.is_blocked(&redemption.user_login, &redemption.user_id) {
+                                ctx.report_dropped(&redemption.user_name, "the user is blocked from TTS");
+                            } else if state.enable_tts && redemption.is_reward(&state.redemption_reward) {
+                                handle_redemption(&ctx, redemption).await;
+                            }
+                        },
                         result = conn.reader.next() => match result {
                             Ok(message) => match message {
                                 Message::Ping(ping) => conn.sender.pong(ping.arg()).await.unwrap(),
                                 Message::Privmsg(message) => {
+                                    let chatter = Chatter::from_tags(|key| message.tags().get(key))
+                                        .with_login(message.prefix().and_then(|prefix| prefix.nick));
+
+                                    blocklist.saw(&chatter.login, &chatter.id);
+
+                                    if chatter.is_moderator() {
+                                        match moderation::parse_mod_command(&state.mod_command_prefix, message.text()) {
+                                            Some(Ok(command)) => {
+                                                ctx.run_mod_command(&mut state, &mut blocklist, &chatter.display_name, command);
+                                                continue;
+                                            }
+                                            Some(Err(e)) => {
+                                                ctx.report_mod_error(&chatter.display_name, e);
+                                                continue;
+                                            }
+                                            None => {}
+                                        }
+                                    }
+                                    if blocklist.is_blocked(&chatter.login, &chatter.id) {
+                                        continue;
+                                    }
+
+                                    let text = emotes::clean(
+                                        message.text(),
+                                        message.tags().get("emotes"),
+                                        third_party_emotes.get(),
+                                        &sound_names,
+                                        state.emote_mode,
+                                    );
+
+                                    // Cheermotes aren't words, even in cheers which are too small to be read.
+                                    let text = if chatter.bits > 0 {
+                                        crate::chat::strip_cheermotes(&text)
+                                    } else {
+                                        text
+                                    };
+
                                     // TODO: avoid this allocation
-                                    if state.enable_tts && message.text().starts_with(&format!("!{} ", state.command_name)) {
-                                        if let Some(request) = parse_tts_request(&message.text()[state.command_name.len() + 2..]) {
-                                            if !ctx.banned_words.lock().await.check(&request.text) {
-                                                tokio::spawn(make_tts_request(ctx.clone(), request));
+                                    if state.enable_tts && state.bits_threshold > 0 && chatter.bits >= state.bits_threshold {
+                                        // Cheers bypass the command cooldown, the chatter has already paid for them.
+                                        match parse_cheer_request(&state, &text) {
+                                            Some(requests) => submit(&ctx, &chatter, requests, true).await,
+                                            None => ctx.report_dropped(&chatter.display_name, "the cheer has no text or an unknown speaker"),
+                                        }
+                                    } else if state.enable_tts && text.starts_with(&format!("!{} ", state.command_name)) {
+                                        if let Some(requests) = parse_tts_request(&text[state.command_name.len() + 2..], &state.default_speaker) {
+                                            // Moderators and the broadcaster are exempt from the cooldown.
+                                            let on_cooldown = if chatter.is_moderator() {
+                                                None
+                                            } else {
+                                                cooldown.try_use(&chatter.id).err()
+                                            };
+                                            match on_cooldown {
+                                                None => submit(&ctx, &chatter, requests, true).await,
+                                                Some(remaining) => ctx.report_dropped(
+                                                    &chatter.display_name,
+                                                    format!("on cooldown for another {}s", remaining.as_secs() + 1),
+                                                ),
                                             }
                                         }
                                     }
 
                                 }
+                                Message::UserNotice(notice) => {
+                                    if state.enable_tts && state.enable_sub_tts {
+                                        if let Some(subscription) = Subscription::from_tags(|key| notice.tags().get(key)) {
+                                            // Subscriptions bypass the command cooldown.
+                                            match parse_sub_request(&state, &subscription, notice.text().unwrap_or_default()) {
+                                                Some(request) => {
+                                                    let chatter = Chatter {
+                                                        display_name: subscription.user.clone(),
+                                                        ..Chatter::from_tags(|key| notice.tags().get(key))
+                                                    };
+                                                    // The sub templates already contain the name.
+                                                    submit(&ctx, &chatter, vec![request], false).await
+                                                }
+                                                None => ctx.report_dropped(&subscription.user, "the sub speaker is unknown or there is nothing to read"),
+                                            }
+                                        }
+                                    }
+                                }
                                 _ => (),
                             },
                             Err(err) => {
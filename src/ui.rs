@@ -1,16 +1,89 @@
-use crate::msg;
+use crate::{
+    backend::{self, BackendKind},
+    browser_output::OutputMode,
+    chat::Chatter,
+    emotes::EmoteMode,
+    filter::CensorPolicy,
+    limits::LengthPolicy,
+    msg, normalize, output_device,
+    pronunciation::{self, Pronunciation},
+    pubsub,
+    queue::{Segment, Status},
+    sounds, speakers, tts,
+    word_lists::{self, WordLists},
+};
 use chrono::{DateTime, Duration, Utc};
 use eframe::{egui, epi};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+
+const MAX_LOG_ENTRIES: usize = 100;
 
 #[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct State {
     pub token: Option<String>,
     pub channel: String,
     pub command_name: String,
     pub command_cooldown: String,
     pub enable_tts: bool,
+    pub backend: BackendKind,
+    /// The ID or title of the channel point reward which triggers TTS.
+    pub redemption_reward: String,
+    pub pubsub_url: String,
+    /// Used to find the channel ID for PubSub.
+    pub validate_url: String,
+    /// The minimum amount of bits for a cheer to be read, `0` disables cheers.
+    pub bits_threshold: u32,
+    /// The speaker used for cheers without a `<speaker>:` prefix, instead of `default_speaker`.
+    pub cheer_speaker: String,
+    /// The speaker used for messages without a `<speaker>:` prefix, empty to ignore them.
+    pub default_speaker: String,
+    pub emote_mode: EmoteMode,
+    /// Read the name of the chatter before the message.
+    pub announce_user: bool,
+    /// See `tts::announcement` for the placeholders.
+    pub announce_template: String,
+    /// Empty to use the voice of the message.
+    pub announcer_speaker: String,
+    /// Lowercase display name -> how to say it.
+    pub name_pronunciations: BTreeMap<String, String>,
+    pub enable_sub_tts: bool,
+    /// Empty to use `default_speaker`.
+    pub sub_speaker: String,
+    /// Templates for subscription notices, see `tts::parse_sub_request` for the placeholders.
+    pub sub_template: String,
+    pub resub_template: String,
+    pub gift_template: String,
+    pub mystery_gift_template: String,
+    /// The prefix of the moderator chat commands, e.g. `!tts skip`.
+    pub mod_command_prefix: String,
+    /// Whether the standard list of the censor crate is used in addition to bannedwords.txt.
+    pub include_standard_words: bool,
+    pub censor_policy: CensorPolicy,
+    /// Replaces banned words when `censor_policy` is `CensorPolicy::Substitute`.
+    pub censor_substitute: String,
+    /// Hold every request until the streamer approves it, except for the auto-approved users.
+    pub hold_for_approval: bool,
+    pub auto_approve_mods: bool,
+    pub auto_approve_vips: bool,
+    pub auto_approve_subs: bool,
+    pub output: OutputMode,
+    /// The name of the output device, or `None` for the default device.
+    pub output_device: Option<String>,
+    pub volume: f32,
+    pub speed: f32,
+    /// The volume of each voice, relative to `volume`. Voices which aren't in the table are unchanged.
+    pub speaker_gain: BTreeMap<String, f32>,
+    pub normalize_loudness: bool,
+    /// The maximum number of characters in a message, `0` means no limit.
+    pub max_message_length: usize,
+    pub long_message_policy: LengthPolicy,
+    /// Longer clips are cut off with a fade-out, `0` means no limit.
+    pub max_clip_seconds: u64,
 }
 
 impl Default for State {
@@ -21,6 +94,41 @@ impl Default for State {
             command_name: "tts".to_string(),
             command_cooldown: "0".to_string(),
             enable_tts: true,
+            backend: BackendKind::default(),
+            redemption_reward: "".to_string(),
+            pubsub_url: pubsub::PUBSUB_URL.to_string(),
+            validate_url: pubsub::VALIDATE_URL.to_string(),
+            bits_threshold: 0,
+            cheer_speaker: "".to_string(),
+            default_speaker: "".to_string(),
+            emote_mode: EmoteMode::default(),
+            announce_user: false,
+            announce_template: "{user} says".to_string(),
+            announcer_speaker: "".to_string(),
+            name_pronunciations: BTreeMap::new(),
+            enable_sub_tts: false,
+            sub_speaker: "".to_string(),
+            sub_template: "{user} subscribed: {message}".to_string(),
+            resub_template: "{user} resubscribed for {months} months: {message}".to_string(),
+            gift_template: "{user} gifted a sub to {recipient}".to_string(),
+            mystery_gift_template: "{user} gifted {count} subs to the community".to_string(),
+            mod_command_prefix: "!tts".to_string(),
+            include_standard_words: true,
+            censor_policy: CensorPolicy::default(),
+            censor_substitute: "bleep".to_string(),
+            hold_for_approval: false,
+            auto_approve_mods: true,
+            auto_approve_vips: false,
+            auto_approve_subs: false,
+            output: OutputMode::default(),
+            output_device: None,
+            volume: 1.0,
+            speed: 1.0,
+            speaker_gain: BTreeMap::new(),
+            normalize_loudness: false,
+            max_message_length: 300,
+            long_message_policy: LengthPolicy::default(),
+            max_clip_seconds: 30,
         }
     }
 }
@@ -39,6 +147,10 @@ impl State {
         }
         State::default()
     }
+    pub fn speaker_gain(&self, speaker: &str) -> f32 {
+        self.speaker_gain.get(speaker).copied().unwrap_or(1.0)
+    }
+
     pub fn save(data: &str) -> String {
         format!(
             "// Do not modify this file.\nexport const Config = JSON.parse(\n`{}`\n);",
@@ -67,6 +179,20 @@ pub struct App {
     tts: crate::tts::TtsCtx,
     msg: msg::Receiver,
     state: State,
+    log: VecDeque<String>,
+    word_lists: WordLists,
+    new_banned_word: String,
+    new_allowed_word: String,
+    output_devices: Vec<String>,
+    sounds: Vec<String>,
+    new_gain_speaker: String,
+    pronunciations: Vec<Pronunciation>,
+    preview_text: String,
+    preview_speaker: String,
+    test_speaker: String,
+    test_text: String,
+    new_name_user: String,
+    new_name_spoken: String,
 
     _clipboard_text_timer: Timer,
     _save_text_timer: Timer,
@@ -79,11 +205,26 @@ impl App {
         msg: msg::Receiver,
         state: State,
     ) -> App {
+        tts.update_tts_config(state.clone());
         App {
             rt,
             tts,
             msg,
             state,
+            log: VecDeque::new(),
+            word_lists: WordLists::load(),
+            new_banned_word: String::new(),
+            new_allowed_word: String::new(),
+            output_devices: output_device::list(),
+            sounds: sounds::list(),
+            new_gain_speaker: String::new(),
+            pronunciations: pronunciation::load(),
+            preview_text: String::new(),
+            preview_speaker: String::new(),
+            test_speaker: String::new(),
+            test_text: String::new(),
+            new_name_user: String::new(),
+            new_name_spoken: String::new(),
 
             _clipboard_text_timer: Timer::new(),
             _save_text_timer: Timer::new(),
@@ -114,6 +255,420 @@ const AUTH_URI: &str = concat!(
 );
 
 impl App {
+    fn push_log(&mut self, entry: String) {
+        if self.log.len() == MAX_LOG_ENTRIES {
+            self.log.pop_front();
+        }
+        self.log.push_back(format!(
+            "{} {}",
+            chrono::Local::now().format("%H:%M:%S"),
+            entry
+        ));
+    }
+
+    fn queue_ui(&mut self, ui: &mut egui::Ui) {
+        enum Action {
+            Skip,
+            Remove(u64),
+            Move(u64, bool),
+            Approve(u64),
+            Reject(u64),
+        }
+        let mut action = None;
+
+        egui::ScrollArea::from_max_height(150.).show(ui, |ui| {
+            let queue = self.tts.queue.lock().unwrap();
+            egui::Grid::new("queue").striped(true).show(ui, |ui| {
+                for item in queue.items() {
+                    ui.label(item.status.label());
+                    ui.label(format!("{} ({})", item.requester, item.speakers()));
+                    let text = item.text();
+                    ui.label(text.chars().take(40).collect::<String>())
+                        .on_hover_text(item.error.as_deref().unwrap_or(&text));
+                    ui.horizontal(|ui| {
+                        if item.status == Status::AwaitingApproval {
+                            if ui.small_button("✔").on_hover_text("Approve").clicked() {
+                                action = Some(Action::Approve(item.id));
+                            }
+                            if ui.small_button("✖").on_hover_text("Reject").clicked() {
+                                action = Some(Action::Reject(item.id));
+                            }
+                        } else if item.status == Status::Playing {
+                            if ui.small_button("⏭").on_hover_text("Skip").clicked() {
+                                action = Some(Action::Skip);
+                            }
+                        } else {
+                            if ui.small_button("⬆").clicked() {
+                                action = Some(Action::Move(item.id, true));
+                            }
+                            if ui.small_button("⬇").clicked() {
+                                action = Some(Action::Move(item.id, false));
+                            }
+                            if ui.small_button("✖").on_hover_text("Remove").clicked() {
+                                action = Some(Action::Remove(item.id));
+                            }
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        });
+
+        match action {
+            Some(Action::Skip) => self.tts.skip(),
+            Some(Action::Remove(id)) => self.tts.remove(id),
+            Some(Action::Move(id, up)) => self.tts.move_item(id, up),
+            Some(Action::Approve(id)) => {
+                self.rt.spawn(tts::approve(&self.tts, id));
+            }
+            Some(Action::Reject(id)) => tts::reject(&self.tts, id),
+            None => {}
+        }
+    }
+
+    fn audio_ui(&mut self, ui: &mut egui::Ui) {
+        let previous_output = self.state.output;
+        egui::ComboBox::from_label("Play TTS on")
+            .selected_text(self.state.output.name())
+            .show_ui(ui, |ui| {
+                for output in OutputMode::ALL.iter() {
+                    ui.selectable_value(&mut self.state.output, *output, output.name());
+                }
+            });
+        if self.state.output != previous_output {
+            self.tts.update_tts_config(self.state.clone());
+        }
+        match self.state.output {
+            OutputMode::Device => {
+                let previous_device = self.state.output_device.clone();
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_label("Device")
+                        .selected_text(self.state.output_device.as_deref().unwrap_or("Default"))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.state.output_device, None, "Default");
+                            for device in &self.output_devices {
+                                ui.selectable_value(
+                                    &mut self.state.output_device,
+                                    Some(device.clone()),
+                                    device,
+                                );
+                            }
+                        });
+                    if ui.button("Refresh").clicked() {
+                        self.output_devices = output_device::list();
+                    }
+                });
+                if self.state.output_device != previous_device {
+                    self.tts.update_tts_config(self.state.clone());
+                }
+            }
+            OutputMode::Browser => {
+                ui.label("Add http://localhost:3030/audio as a browser source in OBS.");
+            }
+        }
+
+        // The sliders are only applied once they're let go, instead of on every frame of the drag.
+        let mut changed = false;
+        changed |=
+            committed(ui.add(egui::Slider::new(&mut self.state.volume, 0.0..=2.0).text("Volume")));
+        changed |=
+            committed(ui.add(egui::Slider::new(&mut self.state.speed, 0.5..=2.0).text("Speed")));
+        changed |= ui
+            .checkbox(&mut self.state.normalize_loudness, "Normalize loudness")
+            .changed();
+
+        ui.label("Speaker gain");
+        let mut removed = None;
+        egui::Grid::new("speaker_gain").show(ui, |ui| {
+            for (speaker, gain) in self.state.speaker_gain.iter_mut() {
+                ui.label(speaker.as_str());
+                changed |= committed(ui.add(egui::Slider::new(gain, 0.0..=2.0)));
+                if ui.small_button("🗑").clicked() {
+                    removed = Some(speaker.clone());
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(speaker) = removed {
+            self.state.speaker_gain.remove(&speaker);
+            changed = true;
+        }
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_id_source("new_gain_speaker")
+                .selected_text(self.new_gain_speaker.as_str())
+                .show_ui(ui, |ui| {
+                    for voice in speakers::voices() {
+                        ui.selectable_value(&mut self.new_gain_speaker, voice.clone(), voice);
+                    }
+                });
+            if ui.button("Add").clicked() && !self.new_gain_speaker.is_empty() {
+                self.state
+                    .speaker_gain
+                    .entry(self.new_gain_speaker.clone())
+                    .or_insert(1.0);
+                changed = true;
+            }
+        });
+
+        if changed {
+            self.tts.update_tts_config(self.state.clone());
+        }
+    }
+
+    /// Lets the streamer send a request as if it came from chat, without going live.
+    fn test_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Speaker")
+                .selected_text(self.test_speaker.as_str())
+                .show_ui(ui, |ui| {
+                    for (alias, voice) in speakers::all() {
+                        let label = format!("{} ({})", alias, voice);
+                        ui.selectable_value(&mut self.test_speaker, alias, label);
+                    }
+                });
+        });
+        let submitted = ui
+            .add(egui::TextEdit::singleline(&mut self.test_text).hint_text("Text"))
+            .lost_focus()
+            && ui.input().key_pressed(egui::Key::Enter);
+        ui.label("Emotes aren't removed from test messages.");
+        if (ui.button("Speak").clicked() || submitted) && !self.test_speaker.is_empty() {
+            // The text isn't parsed for `|`, so it's read exactly as typed, by the chosen speaker.
+            let text = normalize::normalize(&self.test_text);
+            match speakers::resolve(&self.test_speaker) {
+                _ if text.is_empty() => self.push_log("Test: there is nothing to read".to_string()),
+                None => self.push_log(format!("Test: unknown speaker {}", self.test_speaker)),
+                Some(speaker) => {
+                    // The same censor and queue as a chat message.
+                    let requests = vec![tts::TtsRequest { text, speaker }];
+                    let chatter = Chatter {
+                        display_name: "Test".to_string(),
+                        badges: vec!["broadcaster".to_string()],
+                        ..Chatter::default()
+                    };
+                    let ctx = self.tts.clone();
+                    self.rt
+                        .spawn(async move { tts::submit(&ctx, &chatter, requests, true).await });
+                }
+            }
+        }
+    }
+
+    fn announcement_ui(&mut self, ui: &mut egui::Ui) {
+        let mut changed = ui
+            .checkbox(
+                &mut self.state.announce_user,
+                "Read the name before the message",
+            )
+            .changed();
+        egui::Grid::new("announcement").show(ui, |ui| {
+            ui.label("Template");
+            changed |= ui
+                .text_edit_singleline(&mut self.state.announce_template)
+                .lost_focus();
+            ui.end_row();
+            ui.label("Announcer");
+            changed |= ui
+                .text_edit_singleline(&mut self.state.announcer_speaker)
+                .lost_focus();
+            ui.end_row();
+        });
+        ui.label(
+            "Placeholders: {user}. Leave the announcer empty to use the voice of the message.",
+        );
+
+        ui.label("Name pronunciations");
+        let mut removed = None;
+        egui::Grid::new("name_pronunciations").show(ui, |ui| {
+            for (user, spoken) in self.state.name_pronunciations.iter_mut() {
+                ui.label(user.as_str());
+                changed |= ui.text_edit_singleline(spoken).lost_focus();
+                if ui.small_button("🗑").clicked() {
+                    removed = Some(user.clone());
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(user) = removed {
+            self.state.name_pronunciations.remove(&user);
+            changed = true;
+        }
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.new_name_user).hint_text("User"));
+            ui.add(egui::TextEdit::singleline(&mut self.new_name_spoken).hint_text("Say as"));
+            let user = self.new_name_user.trim().to_lowercase();
+            if ui.button("Add").clicked() && !user.is_empty() {
+                self.state
+                    .name_pronunciations
+                    .insert(user, self.new_name_spoken.trim().to_string());
+                self.new_name_user.clear();
+                self.new_name_spoken.clear();
+                changed = true;
+            }
+        });
+
+        if changed {
+            self.tts.update_tts_config(self.state.clone());
+        }
+    }
+
+    fn pronunciation_ui(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let mut removed = None;
+        egui::Grid::new("pronunciations").show(ui, |ui| {
+            ui.label("Word or pattern");
+            ui.label("Pronounced as");
+            ui.label("Regex");
+            ui.end_row();
+            for (i, entry) in self.pronunciations.iter_mut().enumerate() {
+                changed |= ui.text_edit_singleline(&mut entry.pattern).lost_focus();
+                changed |= ui.text_edit_singleline(&mut entry.respelling).lost_focus();
+                changed |= ui.checkbox(&mut entry.regex, "").changed();
+                if ui.small_button("🗑").clicked() {
+                    removed = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if let Some(i) = removed {
+            self.pronunciations.remove(i);
+            changed = true;
+        }
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                self.pronunciations.push(Pronunciation::default());
+            }
+            if ui.button("Reload from disk").clicked() {
+                self.pronunciations = pronunciation::load();
+                changed = true;
+            }
+        });
+        if changed {
+            self.tts.set_pronunciations(&self.pronunciations);
+        }
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.add(egui::TextEdit::singleline(&mut self.preview_text).hint_text("Preview text"));
+            egui::ComboBox::from_id_source("preview_speaker")
+                .selected_text(self.preview_speaker.as_str())
+                .show_ui(ui, |ui| {
+                    for voice in speakers::voices() {
+                        ui.selectable_value(&mut self.preview_speaker, voice.clone(), voice);
+                    }
+                });
+            if ui.button("Preview").clicked()
+                && !self.preview_text.trim().is_empty()
+                && !self.preview_speaker.is_empty()
+            {
+                // Goes through the queue, where the dictionary is applied before synthesis.
+                let segments = vec![Segment::Speech(tts::TtsRequest {
+                    text: normalize::normalize(&self.preview_text),
+                    speaker: self.preview_speaker.clone(),
+                })];
+                let ctx = self.tts.clone();
+                self.rt.spawn(async move {
+                    tts::make_tts_request(&ctx, "Preview", segments, Status::Pending);
+                });
+            }
+        });
+        ui.label("The preview is played on stream, like any other request.");
+        if !self.preview_text.is_empty() {
+            let text = normalize::normalize(&self.preview_text);
+            ui.label(format!("Sent as: {}", self.tts.respell(&text)));
+        }
+    }
+
+    fn word_lists_ui(&mut self, ui: &mut egui::Ui) {
+        if ui
+            .checkbox(
+                &mut self.state.include_standard_words,
+                "Include the standard list",
+            )
+            .changed()
+        {
+            self.tts.update_tts_config(self.state.clone());
+        }
+
+        let previous_policy = self.state.censor_policy;
+        egui::ComboBox::from_label("When a message contains banned words")
+            .selected_text(self.state.censor_policy.name())
+            .show_ui(ui, |ui| {
+                for policy in CensorPolicy::ALL.iter() {
+                    ui.selectable_value(&mut self.state.censor_policy, *policy, policy.name());
+                }
+            });
+        if self.state.censor_policy != previous_policy {
+            self.tts.update_tts_config(self.state.clone());
+        }
+        if self.state.censor_policy == CensorPolicy::Substitute
+            && ui
+                .add(
+                    egui::TextEdit::singleline(&mut self.state.censor_substitute)
+                        .hint_text("Substitute word"),
+                )
+                .lost_focus()
+        {
+            self.tts.update_tts_config(self.state.clone());
+        }
+
+        let mut changed = false;
+        ui.columns(2, |columns| {
+            let (left, right) = columns.split_at_mut(1);
+            for (ui, label, list, new_word) in [
+                (
+                    &mut left[0],
+                    "Banned",
+                    &mut self.word_lists.banned,
+                    &mut self.new_banned_word,
+                ),
+                (
+                    &mut right[0],
+                    "Allowed",
+                    &mut self.word_lists.allowed,
+                    &mut self.new_allowed_word,
+                ),
+            ] {
+                ui.label(label);
+                ui.horizontal(|ui| {
+                    let submitted = ui.text_edit_singleline(new_word).lost_focus()
+                        && ui.input().key_pressed(egui::Key::Enter);
+                    if (ui.small_button("➕").clicked() || submitted)
+                        && word_lists::add_word(list, new_word)
+                    {
+                        new_word.clear();
+                        changed = true;
+                    }
+                });
+                egui::ScrollArea::from_max_height(100.)
+                    .id_source(label)
+                    .show(ui, |ui| {
+                        let mut removed = None;
+                        for (i, word) in list.iter().enumerate() {
+                            ui.horizontal(|ui| {
+                                if ui.small_button("✖").clicked() {
+                                    removed = Some(i);
+                                }
+                                ui.label(word.as_str());
+                            });
+                        }
+                        if let Some(i) = removed {
+                            list.remove(i);
+                            changed = true;
+                        }
+                    });
+            }
+        });
+
+        if changed {
+            // The TTS thread picks up the change from the files.
+            self.word_lists.save();
+        }
+        if ui.button("Reload from disk").clicked() {
+            self.word_lists = WordLists::load();
+        }
+    }
+
     fn save_config(&self) {
         let config = serde_json::to_string(&self.state).expect("Failed to serialize config");
         std::fs::write(crate::get_config_file_path(), State::save(&config))
@@ -131,9 +686,23 @@ impl epi::App for App {
             match msg {
                 msg::Message::Auth { token } => {
                     self.state.token = Some(token);
+                    self.tts.update_tts_config(self.state.clone());
+                }
+                msg::Message::Error { what } => {
+                    log::error!("{}", what);
+                    self.push_log(what);
+                }
+                msg::Message::Moderation { moderator, action } => {
+                    self.push_log(format!("{} {}", moderator, action));
+                }
+                msg::Message::TtsEnabled(enabled) => {
+                    self.state.enable_tts = enabled;
                 }
-                msg::Message::Error { what: _ } => {
-                    todo!()
+                msg::Message::Censored { user, decision } => {
+                    self.push_log(format!("Censored request from {}: {}", user, decision));
+                }
+                msg::Message::Dropped { user, reason } => {
+                    self.push_log(format!("Dropped request from {}: {}", user, reason));
                 }
             }
         }
@@ -164,6 +733,7 @@ impl epi::App for App {
                 }
                 if self.state.token.is_some() && ui.button("Reset").clicked() {
                     self.state.token = None;
+                    self.tts.update_tts_config(self.state.clone());
                 }
             });
 
@@ -194,10 +764,15 @@ impl epi::App for App {
                         self.tts.update_tts_config(self.state.clone());
                     }
 
-                    ui.add(
-                        egui::TextEdit::singleline(&mut self.state.command_cooldown)
-                            .hint_text("Command cooldown"),
-                    );
+                    if ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.state.command_cooldown)
+                                .hint_text("Cooldown: user seconds[, global seconds]"),
+                        )
+                        .lost_focus()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
 
                     if ui
                         .checkbox(&mut self.state.enable_tts, "Enable TTS Command")
@@ -205,15 +780,89 @@ impl epi::App for App {
                     {
                         self.tts.update_tts_config(self.state.clone());
                     }
+
+                    let previous_backend = self.state.backend.clone();
+                    let command_backend = match &self.state.backend {
+                        BackendKind::Command { .. } => self.state.backend.clone(),
+                        _ => BackendKind::Command {
+                            template: backend::DEFAULT_COMMAND_TEMPLATE.to_string(),
+                        },
+                    };
+                    egui::ComboBox::from_label("Backend")
+                        .selected_text(self.state.backend.name())
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut self.state.backend,
+                                BackendKind::Mumble,
+                                BackendKind::Mumble.name(),
+                            );
+                            let name = command_backend.name();
+                            ui.selectable_value(&mut self.state.backend, command_backend, name);
+                        });
+                    if self.state.backend != previous_backend {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+                    if ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.state.redemption_reward)
+                                .hint_text("Channel point reward title or ID"),
+                        )
+                        .lost_focus()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+
+                    if ui
+                        .add(
+                            egui::Slider::new(&mut self.state.bits_threshold, 0..=10000)
+                                .text("Min. bits"),
+                        )
+                        .changed()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+                    if ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.state.cheer_speaker)
+                                .hint_text("Cheer speaker"),
+                        )
+                        .lost_focus()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+                    if ui
+                        .add(
+                            egui::TextEdit::singleline(&mut self.state.default_speaker)
+                                .hint_text("Default speaker, or random"),
+                        )
+                        .lost_focus()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+
+                    if let BackendKind::Command { template } = &mut self.state.backend {
+                        if ui
+                            .add(
+                                egui::TextEdit::singleline(template)
+                                    .hint_text("Command, e.g. espeak-ng --stdout {text}"),
+                            )
+                            .lost_focus()
+                        {
+                            self.tts.update_tts_config(self.state.clone());
+                        }
+                    }
                 });
 
                 ui.separator();
 
                 ui.vertical_centered_justified(|ui| {
-                    ui.heading(format!("TTS Queue ({} pending)", self.tts.queue.len()));
+                    ui.heading(format!(
+                        "TTS Queue ({} pending)",
+                        self.tts.queue.lock().unwrap().len()
+                    ));
 
                     // Play/Pause
-                    let is_paused = self.tts.queue.is_paused(); // an atomic load, so it's okay to call in the ui loop
+                    let is_paused = self.tts.sink.is_paused(); // an atomic load, so it's okay to call in the ui loop
                     if ui
                         .button(if is_paused {
                             "Resume TTS ▶"
@@ -223,24 +872,208 @@ impl epi::App for App {
                         .clicked()
                     {
                         if is_paused {
-                            self.tts.queue.play();
+                            self.tts.sink.play();
                         } else {
-                            self.tts.queue.pause();
+                            self.tts.sink.pause();
                         }
                     }
 
                     ui.separator();
 
-                    if ui.button("Stop TTS ⏹").clicked() {
-                        self.tts.queue.stop();
+                    if ui.button("Skip ⏭").clicked() {
+                        self.tts.skip();
+                    }
+                    if ui.button("Clear queue ⏹").clicked() {
+                        self.tts.clear();
                     }
                 });
             });
 
             ui.separator();
 
-            ui.horizontal(|ui| {
-                ui.label("TODO: bannedwords.txt checkbox");
+            self.queue_ui(ui);
+
+            ui.separator();
+
+            ui.collapsing("Test TTS", |ui| self.test_ui(ui));
+
+            ui.collapsing("Audio output", |ui| self.audio_ui(ui));
+
+            ui.collapsing("Banned words", |ui| self.word_lists_ui(ui));
+
+            ui.collapsing("Subscriptions", |ui| {
+                let mut changed = ui
+                    .checkbox(&mut self.state.enable_sub_tts, "Read subscriptions")
+                    .changed();
+                egui::Grid::new("sub_templates").show(ui, |ui| {
+                    for (label, value) in [
+                        ("Speaker", &mut self.state.sub_speaker),
+                        ("Sub", &mut self.state.sub_template),
+                        ("Resub", &mut self.state.resub_template),
+                        ("Gift", &mut self.state.gift_template),
+                        ("Community gift", &mut self.state.mystery_gift_template),
+                    ] {
+                        ui.label(label);
+                        changed |= ui.text_edit_singleline(value).lost_focus();
+                        ui.end_row();
+                    }
+                });
+                ui.label("Placeholders: {user}, {months}, {recipient}, {count}, {message}");
+                if changed {
+                    self.tts.update_tts_config(self.state.clone());
+                }
+            });
+
+            ui.collapsing("Approval", |ui| {
+                let mut changed = ui
+                    .checkbox(&mut self.state.hold_for_approval, "Hold requests for approval")
+                    .changed();
+                ui.label("Auto-approve:");
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .checkbox(&mut self.state.auto_approve_mods, "Moderators")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut self.state.auto_approve_vips, "VIPs")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut self.state.auto_approve_subs, "Subscribers")
+                        .changed();
+                });
+                ui.hyperlink_to("Open the approval dock", "http://localhost:3030/dock");
+                if changed {
+                    self.tts.update_tts_config(self.state.clone());
+                }
+            });
+
+            ui.collapsing("Speakers", |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Refresh").clicked() {
+                        self.rt.spawn(speakers::refresh(self.tts.backend()));
+                    }
+                    if ui.button("Edit aliases").clicked() {
+                        let _ = open::that(speakers::get_aliases_path());
+                    }
+                });
+                egui::ScrollArea::from_max_height(160.).show(ui, |ui| {
+                    egui::Grid::new("speakers").show(ui, |ui| {
+                        for (alias, voice) in speakers::all() {
+                            ui.label(alias);
+                            ui.label(voice);
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+
+            ui.collapsing("Sound effects", |ui| {
+                ui.label("Put sound files in the sounds folder and use them with (name) in a message.");
+                ui.horizontal(|ui| {
+                    if ui.button("Open the sounds folder").clicked() {
+                        let _ = open::that(sounds::get_sounds_dir_path());
+                    }
+                    if ui.button("Refresh").clicked() {
+                        self.sounds = sounds::list();
+                    }
+                });
+                if self.sounds.is_empty() {
+                    ui.label("No sound effects yet.");
+                } else {
+                    ui.label(self.sounds.join(", "));
+                }
+            });
+
+            ui.collapsing("Pronunciation", |ui| self.pronunciation_ui(ui));
+
+            ui.collapsing("Announcement", |ui| self.announcement_ui(ui));
+
+            ui.collapsing("Emotes", |ui| {
+                let previous_mode = self.state.emote_mode;
+                egui::ComboBox::from_label("Emotes in messages")
+                    .selected_text(self.state.emote_mode.name())
+                    .show_ui(ui, |ui| {
+                        for mode in EmoteMode::ALL.iter() {
+                            ui.selectable_value(&mut self.state.emote_mode, *mode, mode.name());
+                        }
+                    });
+                if self.state.emote_mode != previous_mode {
+                    self.tts.update_tts_config(self.state.clone());
+                }
+                ui.label("Third-party emotes are read from emotes.json, a list of emote names.");
+            });
+
+            ui.collapsing("Limits", |ui| {
+                let previous_policy = self.state.long_message_policy;
+                let mut changed = ui
+                    .add(
+                        egui::Slider::new(&mut self.state.max_message_length, 0..=1000)
+                            .text("Max. characters"),
+                    )
+                    .changed();
+                egui::ComboBox::from_label("When a message is too long")
+                    .selected_text(self.state.long_message_policy.name())
+                    .show_ui(ui, |ui| {
+                        for policy in LengthPolicy::ALL.iter() {
+                            ui.selectable_value(
+                                &mut self.state.long_message_policy,
+                                *policy,
+                                policy.name(),
+                            );
+                        }
+                    });
+                changed |= self.state.long_message_policy != previous_policy;
+                changed |= ui
+                    .add(
+                        egui::Slider::new(&mut self.state.max_clip_seconds, 0..=300)
+                            .text("Max. clip seconds"),
+                    )
+                    .changed();
+                ui.label("0 means no limit.");
+                if changed {
+                    self.tts.update_tts_config(self.state.clone());
+                }
+            });
+
+            ui.collapsing("Moderation", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Command prefix");
+                    if ui
+                        .text_edit_singleline(&mut self.state.mod_command_prefix)
+                        .lost_focus()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+                });
+                ui.label("Commands: skip, pause, resume, clear, disable, enable, ban <user> [minutes], unban <user>");
+            });
+
+            ui.collapsing("Advanced", |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("PubSub URL");
+                    if ui
+                        .text_edit_singleline(&mut self.state.pubsub_url)
+                        .lost_focus()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Token validation URL");
+                    if ui
+                        .text_edit_singleline(&mut self.state.validate_url)
+                        .lost_focus()
+                    {
+                        self.tts.update_tts_config(self.state.clone());
+                    }
+                });
+            });
+
+            ui.collapsing(format!("Log ({})", self.log.len()), |ui| {
+                egui::ScrollArea::from_max_height(120.).show(ui, |ui| {
+                    for entry in self.log.iter().rev() {
+                        ui.label(entry.as_str());
+                    }
+                });
             });
             ui.label(if self._clipboard_text_timer.elapsed_milliseconds(1500) {
                 "(click to copy)"
@@ -251,6 +1084,11 @@ impl epi::App for App {
     }
 }
 
+/// Whether the user finished editing a slider, either by letting go of it or by typing a value.
+fn committed(response: egui::Response) -> bool {
+    response.drag_released() || response.lost_focus()
+}
+
 pub fn start(
     rt: Arc<tokio::runtime::Runtime>,
     tts: crate::tts::TtsCtx,
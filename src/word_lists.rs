@@ -0,0 +1,125 @@
+use censor::Censor;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// How often the lists are checked for changes.
+pub const RELOAD_INTERVAL_SECONDS: u64 = 2;
+/// The words that used to be removed from the standard list, used to create the allowlist.
+pub const DEFAULT_ALLOWED_WORDS: &[&str] = &["ass", "cock", "pussy", "fuck", "piss", "shit"];
+
+pub fn get_banned_words_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("bannedwords.txt");
+    path
+}
+
+pub fn get_allowed_words_path() -> PathBuf {
+    let mut path = crate::get_config_dir_path();
+    path.push("allowedwords.txt");
+    path
+}
+
+/// The user-editable banned words and allowlist, one word per line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WordLists {
+    pub banned: Vec<String>,
+    pub allowed: Vec<String>,
+}
+
+impl WordLists {
+    /// Load both lists, creating the allowlist with the defaults if it doesn't exist yet.
+    pub fn load() -> WordLists {
+        let allowed_path = get_allowed_words_path();
+        if !allowed_path.exists() {
+            write_list(&allowed_path, DEFAULT_ALLOWED_WORDS);
+        }
+        WordLists {
+            banned: read_list(&get_banned_words_path()),
+            allowed: read_list(&allowed_path),
+        }
+    }
+
+    pub fn save(&self) {
+        write_list(&get_banned_words_path(), &self.banned);
+        write_list(&get_allowed_words_path(), &self.allowed);
+    }
+
+    /// The last time either file was modified, used to detect changes.
+    pub fn modified() -> (Option<SystemTime>, Option<SystemTime>) {
+        let modified = |path: PathBuf| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        (
+            modified(get_banned_words_path()),
+            modified(get_allowed_words_path()),
+        )
+    }
+
+    pub fn build_censor(&self, include_standard: bool) -> Censor {
+        let empty: Vec<&str> = Vec::new();
+        let mut censor = if include_standard {
+            censor::Standard
+        } else {
+            Censor::custom(empty)
+        };
+        if !self.banned.is_empty() {
+            censor = censor + Censor::custom(self.banned.iter().map(String::as_str).collect());
+        }
+        for word in &self.allowed {
+            censor = censor - word.as_str();
+        }
+        censor
+    }
+}
+
+/// Add `word` to `list` if it isn't there yet.
+pub fn add_word(list: &mut Vec<String>, word: &str) -> bool {
+    let word = word.trim().to_lowercase();
+    if word.is_empty() || list.contains(&word) {
+        return false;
+    }
+    list.push(word);
+    true
+}
+
+fn read_list(path: &Path) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim())
+        // Allow comments, so the file can explain itself.
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_lowercase())
+        .collect()
+}
+
+/// Comments, blank lines and the words which are still in `words` keep their place in the
+/// file, removed words are left out, and new words are added at the end.
+fn write_list<S: AsRef<str>>(path: &Path, words: &[S]) {
+    let existing = std::fs::read_to_string(path).unwrap_or_default();
+    let mut written = HashSet::new();
+    let mut content = String::new();
+    for line in existing.lines() {
+        let trimmed = line.trim();
+        let keep = if trimmed.is_empty() || trimmed.starts_with('#') {
+            true
+        } else {
+            let word = trimmed.to_lowercase();
+            words.iter().any(|w| w.as_ref() == word) && written.insert(word)
+        };
+        if keep {
+            content.push_str(line);
+            content.push('\n');
+        }
+    }
+    for word in words {
+        if written.insert(word.as_ref().to_string()) {
+            content.push_str(word.as_ref());
+            content.push('\n');
+        }
+    }
+    if let Err(e) = std::fs::write(path, content) {
+        log::error!("Failed to write {}: {}", path.display(), e);
+    }
+}